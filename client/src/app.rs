@@ -1,37 +1,315 @@
-use crate::lib::FileExplorer;
 use crate::lib::{
-    FileExplorer::get_dir_contents,
-    NetUtils::{self, sendSong},
+    AudioFile::{self, ProbeInfo, Support},
+    Codec::Codec,
+    Download::{hash_file, Download, Outcome},
+    Playlist::{self, PlaylistEntry},
+    Ratings::{self, Ratings as SongRatings},
+    FileExplorer::{Entry, Explorer, SortKey},
+    FuzzySearch::{fuzzy_match, Match, Search},
+    History::{self, HistoryEntry},
+    Keymap::{Key, Keymap},
+    Logging::{self, RecentLog},
+    Sync::{diff, LocalHashes, LocalSong, SyncEntry, SyncStatus},
+    Theme::Theme,
+    Watcher::LibraryWatcher,
 };
-use ratatui::widgets::ListState;
-use serde::{Deserialize, Serialize};
+use crate::event::Event;
+use chrono::{Local, TimeZone};
+use ratatui::{
+    layout::{Position, Rect},
+    widgets::ListState,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::{
+    collections::{HashMap, HashSet, VecDeque},
     error,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, Mutex,
+        Arc,
     },
 };
-use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedWriteHalf;
 use tokio::net::TcpStream;
+use tokio::sync::{mpsc, Mutex};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::filter::LevelFilter;
 
 /// Application result type.
 pub type AppResult<T> = std::result::Result<T, Box<dyn error::Error>>;
 
 // Information given with server updates
-#[derive(Deserialize, Serialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Default)]
 pub struct ServerState {
     pub active_listeners: u8,
     pub song_library: Vec<String>,
     pub song_queue: Vec<String>,
+    /// Tags uploaded along with the songs, keyed by library name
+    #[serde(default)]
+    pub song_metadata: HashMap<String, SongMetadata>,
+    /// Content hashes keyed by library name
+    #[serde(default)]
+    pub song_hashes: HashMap<String, String>,
+    /// Up/down votes of each queued song, in queue order
+    #[serde(default)]
+    pub queue_votes: Vec<Votes>,
+    /// Skip votes on the current song and how many it takes
+    #[serde(default)]
+    pub skip_votes: u8,
+    #[serde(default)]
+    pub skip_needed: u8,
+    /// Nickname of whoever queued each song, in queue order
+    #[serde(default)]
+    pub queued_by: Vec<String>,
+    /// Everyone connected to the radio
+    #[serde(default)]
+    pub listeners: Vec<Listener>,
+    /// Reaction counts of the current song, one per kind
+    #[serde(default)]
+    pub reactions: Vec<u32>,
 }
 
-impl Default for ServerState {
-    fn default() -> Self {
-        Self {
-            active_listeners: 0,
-            song_library: vec![],
-            song_queue: vec![],
+// Roster entry of a connected listener
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+pub struct Listener {
+    pub nickname: String,
+    /// Unix timestamp in seconds
+    pub connected_since: u64,
+}
+
+// Vote tally of a queued song
+#[derive(Deserialize, Serialize, Debug, Default, Clone, Copy)]
+pub struct Votes {
+    pub up: u32,
+    pub down: u32,
+}
+
+// Tags of a song in the server library
+#[derive(Deserialize, Serialize, Debug, Default, Clone)]
+pub struct SongMetadata {
+    #[serde(default)]
+    pub artist: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub album: String,
+    /// Length in seconds
+    #[serde(default)]
+    pub length: u32,
+}
+
+// Server answer to the client handshake
+#[derive(Deserialize, Debug)]
+pub struct Handshake {
+    pub codec: Codec,
+    pub bitrate: u32,
+}
+
+// Chat line, system lines are generated by the server (joins, uploads, ...)
+#[derive(Deserialize, Debug, Clone)]
+pub struct ChatMessage {
+    pub from: String,
+    pub text: String,
+    /// Unix timestamp in seconds
+    pub time: i64,
+    #[serde(default)]
+    pub system: bool,
+}
+
+// Someone reacted to the current song
+#[derive(Deserialize, Debug, Clone)]
+pub struct Reaction {
+    pub from: String,
+    /// Index into the reaction emoji
+    pub kind: usize,
+}
+
+/// Reaction of another listener being animated.
+#[derive(Debug, Clone)]
+pub struct Burst {
+    pub reaction: Reaction,
+    /// Ticks since it arrived
+    pub age: u8,
+}
+
+// Ticks a reaction burst stays on screen
+pub const BURST_TICKS: u8 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// Message for the user, shown as a toast and then kept in the status bar.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub severity: Severity,
+    pub text: String,
+    /// Ticks shown as a toast
+    pub age: u8,
+}
+
+// Ticks a toast stays on screen
+pub const TOAST_TICKS: u8 = 16;
+// Toasts shown at once, the others wait their turn
+pub const TOAST_LIMIT: usize = 3;
+
+// Announces a download, followed by its chunks
+#[derive(Deserialize, Debug)]
+pub struct DownloadInfo {
+    pub name: String,
+    #[serde(default, deserialize_with = "size_field")]
+    pub size: u64,
+    #[serde(default)]
+    pub hash: String,
+    /// Set when the server can't send the song
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Sizes come as decimal strings, JSON numbers of the server are 32 bit.
+fn size_field<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Size {
+        Number(u64),
+        Text(String),
+    }
+    match Size::deserialize(deserializer)? {
+        Size::Number(size) => Ok(size),
+        Size::Text(size) => size.parse().map_err(serde::de::Error::custom),
+    }
+}
+
+// Base64 encoded piece of a download
+#[derive(Deserialize, Debug)]
+pub struct DownloadChunk {
+    pub name: String,
+    pub data: String,
+}
+
+// Every newline delimited message the server sends on the communication socket
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub enum ServerMessage {
+    Handshake { handshake: Handshake },
+    Chat { chat: ChatMessage },
+    Reaction { reaction: Reaction },
+    Download { download: DownloadInfo },
+    DownloadChunk { download_chunk: DownloadChunk },
+    State(ServerState),
+}
+
+// Chat lines kept for scrollback
+const CHAT_HISTORY: usize = 500;
+
+// Files probed per background result
+const PROBE_BATCH: usize = 16;
+
+/// Local songs waiting to be uploaded, one per tick so progress gets drawn.
+#[derive(Debug, Default)]
+pub struct Batch {
+    pub pending: VecDeque<String>,
+    pub total: usize,
+    /// Files that couldn't be uploaded, reported when the batch ends
+    pub failed: Vec<String>,
+}
+
+impl Batch {
+    pub fn done(&self) -> usize {
+        self.total - self.pending.len()
+    }
+}
+
+/// Playlist whose songs aren't all on the server, waiting for a decision.
+#[derive(Debug)]
+pub struct PlaylistImport {
+    pub name: String,
+    /// Library names in playlist order, including the ones still to upload
+    pub songs: Vec<String>,
+    /// Local files the server lacks, with the name they get in the library
+    pub missing: Vec<(PathBuf, String)>,
+    /// Entries found neither on the server nor locally
+    pub unresolved: usize,
+}
+
+/// Diff of the song directory against the server library.
+#[derive(Debug, Default)]
+pub struct SyncView {
+    pub entries: Vec<SyncEntry>,
+    pub state: ListState,
+    /// The song directory is being hashed
+    pub scanning: bool,
+}
+
+/// Songs heard so far, newest first, filtered by a fuzzy query.
+#[derive(Debug, Default)]
+pub struct HistoryView {
+    pub query: String,
+    /// Typing goes into the query while editing
+    pub editing: bool,
+    /// Rows shown, indexing the newest first history
+    pub matches: Vec<Match>,
+    pub state: ListState,
+}
+
+/// Where the last render put each widget, for hit-testing mouse events.
+/// Widgets left out of the layout get an empty area.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Areas {
+    /// Listener count and upload progress above the server explorer
+    pub server_info: Rect,
+    pub server: Rect,
+    pub client: Rect,
+    pub queue_info: Rect,
+    pub queue: Rect,
+    pub listeners: Rect,
+    pub chat: Rect,
+    pub chat_input: Rect,
+    pub gauge: Rect,
+    /// Where the sync, history and log views open
+    pub views: Rect,
+    /// Panel tabs of the compact layout, in `Panel::ALL` order
+    pub tabs: [Rect; 4],
+    pub status: Rect,
+}
+
+// Below this size the panels take turns on screen
+pub const COMPACT_WIDTH: u16 = 100;
+pub const COMPACT_HEIGHT: u16 = 30;
+
+/// Panels that can hold the keyboard focus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Panel {
+    Server,
+    Client,
+    Queue,
+    Chat,
+}
+
+impl Panel {
+    pub const ALL: [Panel; 4] = [Panel::Server, Panel::Client, Panel::Queue, Panel::Chat];
+
+    /// Tab order, left to right on screen
+    pub fn next(self) -> Self {
+        match self {
+            Panel::Server => Panel::Client,
+            Panel::Client => Panel::Queue,
+            Panel::Queue => Panel::Chat,
+            Panel::Chat => Panel::Server,
+        }
+    }
+
+    pub fn previous(self) -> Self {
+        match self {
+            Panel::Server => Panel::Chat,
+            Panel::Client => Panel::Server,
+            Panel::Queue => Panel::Client,
+            Panel::Chat => Panel::Queue,
         }
     }
 }
@@ -41,19 +319,115 @@ impl Default for ServerState {
 pub struct App<'a> {
     /// Is the application running?
     pub running: Arc<AtomicBool>,
-    /// TCP communication connection
-    pub c_connection: Option<Arc<Mutex<TcpStream>>>,
+    /// TCP communication connection (write half, reads go through the event handler)
+    pub c_connection: Option<Arc<Mutex<OwnedWriteHalf>>>,
     /// TCP audio connection
     pub a_connection: Option<Arc<Mutex<TcpStream>>>,
     /// Clients File Explorer State
     pub client_fs_state: ListState,
+    /// Directory, filters and ordering of the clients file explorer
+    pub explorer: Explorer,
+    /// Cached listing of the clients file explorer
+    pub client_entries: Vec<Entry>,
+    /// Refreshes `client_entries` when the explored directory changes
+    pub watcher: Option<LibraryWatcher>,
+    /// Active `/` search, the filtered explorer's state indexes its matches
+    pub search: Option<Search>,
+    /// Rows each explorer displays, see [`App::view`]
+    client_view: Vec<Match>,
+    server_view: Vec<Match>,
     /// Servers File Explorer State
     pub server_fs_state: ListState,
-    /// Which file explorer is selected
-    /// 0 -> server | 1 -> client
-    pub client_fs_selected: bool,
+    /// Server Queue State
+    pub queue_state: ListState,
+    /// Which panel has the keyboard focus
+    pub focus: Panel,
+    /// Keys bound to actions outside of text input
+    pub keymap: Keymap,
+    /// Keys of a sequence typed so far
+    pub pending_keys: Vec<Key>,
+    /// Lines scrolled in the open help overlay
+    pub help_scroll: Option<usize>,
+    /// Latest log entries, for the log viewer
+    pub log: RecentLog,
+    /// Flushes the log file when the app goes away
+    log_guard: Option<WorkerGuard>,
+    /// Lines scrolled back from the newest entry in the open log viewer
+    pub log_scroll: Option<usize>,
+    /// Colors and borders the interface is drawn with
+    pub theme: Theme,
+    /// Panel areas of the last render
+    pub areas: Areas,
+    /// Time and cell of the last click, to tell double clicks
+    pub last_click: Option<(Instant, Position)>,
+    /// Progress gauge shows the song position instead of a percentage
+    pub show_position: bool,
+    /// Terminal too small for every panel at once, only the focused one is shown
+    pub compact: bool,
+    /// Focused panel takes the whole screen
+    pub zoomed: bool,
     /// State received from server
     pub state: ServerState,
+    /// Audio codec agreed on with the server, the audio stream announces it too
+    pub codec: Codec,
+    /// Requested opus bitrate in bits per second (0 -> server default)
+    pub bitrate: u32,
+    /// Name shown to the other listeners
+    pub nickname: String,
+    /// Chat and system messages, oldest first
+    pub chat: VecDeque<ChatMessage>,
+    /// Message being typed in the chat box
+    pub chat_input: String,
+    /// Lines scrolled back from the newest message
+    pub chat_scroll: usize,
+    /// Reactions of other listeners currently animated
+    pub bursts: Vec<Burst>,
+    /// Notifications shown or waiting to be shown as toasts, oldest first
+    pub toasts: VecDeque<Notification>,
+    /// Latest notification, shown in the status bar
+    pub status: Option<Notification>,
+    /// Songs being fetched from the server library, keyed by name
+    pub downloads: HashMap<String, Download>,
+    /// Open sync view
+    pub sync: Option<SyncView>,
+    /// Marked local files and server songs, kept across directories and searches
+    pub client_marks: HashSet<PathBuf>,
+    pub server_marks: HashSet<String>,
+    /// Running batch upload
+    pub upload_batch: Option<Batch>,
+    /// Songs queued once the running batch upload is done
+    pub enqueue_after_batch: Vec<String>,
+    /// Playlist import asking whether to upload missing songs
+    pub playlist_import: Option<PlaylistImport>,
+    /// Clearing the shared queue waits for a confirmation
+    pub confirm_clear_queue: bool,
+    /// Songs heard in this and earlier sessions, oldest first
+    pub history: Vec<HistoryEntry>,
+    /// Song on air, added to the history once it's over
+    pub now_playing: Option<HistoryEntry>,
+    /// Open history view
+    pub history_view: Option<HistoryView>,
+    /// "host:port" of the server, favorites and ratings are kept per server
+    pub server: String,
+    pub ratings: SongRatings,
+    /// Server explorer shows favorites only
+    pub favorites_only: bool,
+    /// Lent to the background scan while it runs
+    local_hashes: Option<LocalHashes>,
+    /// Latest scan of the song directory
+    local_songs: Vec<LocalSong>,
+    /// The song directory changed during a scan
+    rescan_sync: bool,
+    /// Probe results of local files, keyed by path
+    pub probes: HashMap<String, ProbeInfo>,
+    /// Probes changed since the index was last saved
+    pub index_dirty: bool,
+    /// Paths being probed in the background
+    probing: HashSet<String>,
+    /// Where background tasks send their results
+    events: Option<mpsc::UnboundedSender<Event>>,
+    /// Convert files to the server format before uploading
+    pub transcode_uploads: bool,
 
     /// CONSTANTS
     pub song_dir: &'a str,
@@ -66,9 +440,59 @@ impl<'a> Default for App<'a> {
             c_connection: None,
             a_connection: None,
             client_fs_state: ListState::default().with_selected(Some(0)),
+            explorer: Explorer::new("./songs/"),
+            client_entries: vec![],
+            watcher: None,
+            search: None,
+            client_view: vec![],
+            server_view: vec![],
             server_fs_state: ListState::default(),
-            client_fs_selected: true,
+            queue_state: ListState::default(),
+            focus: Panel::Client,
+            keymap: Keymap::default(),
+            pending_keys: vec![],
+            help_scroll: None,
+            log: RecentLog::default(),
+            log_guard: None,
+            log_scroll: None,
+            theme: Theme::default(),
+            areas: Areas::default(),
+            last_click: None,
+            show_position: false,
+            compact: false,
+            zoomed: false,
             state: ServerState::default(),
+            codec: Codec::Raw,
+            bitrate: 0,
+            nickname: std::env::var("USER").unwrap_or_else(|_| "anonymous".to_string()),
+            chat: VecDeque::new(),
+            chat_input: String::new(),
+            chat_scroll: 0,
+            bursts: vec![],
+            toasts: VecDeque::new(),
+            status: None,
+            downloads: HashMap::new(),
+            sync: None,
+            client_marks: HashSet::new(),
+            server_marks: HashSet::new(),
+            upload_batch: None,
+            enqueue_after_batch: vec![],
+            playlist_import: None,
+            confirm_clear_queue: false,
+            history: vec![],
+            now_playing: None,
+            history_view: None,
+            server: String::new(),
+            ratings: SongRatings::default(),
+            favorites_only: false,
+            local_hashes: Some(LocalHashes::default()),
+            local_songs: vec![],
+            rescan_sync: false,
+            probes: HashMap::new(),
+            index_dirty: false,
+            probing: HashSet::new(),
+            events: None,
+            transcode_uploads: false,
             song_dir: "./songs/",
         }
     }
@@ -77,10 +501,108 @@ impl<'a> Default for App<'a> {
 impl<'a> App<'a> {
     /// Constructs a new instance of [`App`].
     pub fn new() -> Self {
-        Self::default()
+        let mut app = Self::default();
+        // Logging starts first to see everything that follows
+        let level = Logging::level();
+        let filter = *level.as_ref().unwrap_or(&LevelFilter::INFO);
+        match Logging::init(&app.log_dir(), filter) {
+            Ok((guard, log)) => {
+                app.log_guard = Some(guard);
+                app.log = log;
+            }
+            Err(err) => app.notify(
+                Severity::Warning,
+                format!("Can't log to {}: {}", app.log_dir(), err),
+            ),
+        }
+        if let Err(err) = level {
+            app.notify(Severity::Warning, err);
+        }
+        app.probes = AudioFile::load_index(&app.index_path());
+        app.history = History::load(&app.history_path());
+        match Keymap::load(&app.keymap_path()) {
+            Ok(keymap) => app.keymap = keymap,
+            Err(problems) => {
+                for problem in problems {
+                    app.notify(
+                        Severity::Warning,
+                        format!("{}: {}", app.keymap_path(), problem),
+                    );
+                }
+                app.notify(Severity::Warning, "Using the default keys".to_string());
+            }
+        }
+        match Theme::load(&app.theme_path()) {
+            Ok(theme) => app.theme = theme,
+            Err(err) => app.notify(
+                Severity::Warning,
+                format!("{}: {}, using the default theme", app.theme_path(), err),
+            ),
+        }
+        app.refresh_client_entries();
+        app
     }
 
-    pub fn add_comm_connection(&mut self, connection: TcpStream) {
+    /// Starts watching the explored directory, changes arrive as [`Event::LocalLibraryChanged`]
+    /// and background probes as [`Event::Probed`].
+    pub fn watch_local_library(&mut self, sender: mpsc::UnboundedSender<Event>) {
+        self.events = Some(sender.clone());
+        self.probe_in_background();
+        match LibraryWatcher::new(sender) {
+            Ok(watcher) => self.watcher = Some(watcher),
+            Err(err) => self.notify(
+                Severity::Error,
+                format!("Can't watch the song directory: {}", err),
+            ),
+        }
+        self.watch_cwd();
+    }
+
+    fn watch_cwd(&mut self) {
+        let Some(watcher) = self.watcher.as_mut() else {
+            return;
+        };
+        if let Err(err) = watcher.watch(&self.explorer.cwd) {
+            let message = format!("Can't watch {}: {}", self.explorer.cwd.display(), err);
+            self.notify(Severity::Error, message);
+        }
+    }
+
+    /// Cached probe results, in a directory the explorer doesn't watch
+    /// so saving them doesn't look like a library change
+    pub fn index_path(&self) -> String {
+        format!("{}.jam_cache/index.json", self.song_dir)
+    }
+
+    pub fn keymap_path(&self) -> String {
+        format!("{}.jam_keys.json", self.song_dir)
+    }
+
+    /// Daily log files, in a directory so they stay out of the explorer
+    pub fn log_dir(&self) -> String {
+        format!("{}.jam_logs", self.song_dir)
+    }
+
+    pub fn theme_path(&self) -> String {
+        format!("{}.jam_theme.json", self.song_dir)
+    }
+
+    pub fn ratings_path(&self) -> String {
+        format!("{}.jam_ratings.json", self.song_dir)
+    }
+
+    /// Switches to the favorites and ratings kept for `server`.
+    pub fn set_server(&mut self, server: &str) {
+        self.server = server.to_string();
+        self.ratings = Ratings::load(&self.ratings_path(), server);
+        self.refresh_view(false);
+    }
+
+    pub fn history_path(&self) -> String {
+        format!("{}.jam_history.jsonl", self.song_dir)
+    }
+
+    pub fn add_comm_connection(&mut self, connection: OwnedWriteHalf) {
         self.c_connection = Some(Arc::new(Mutex::new(connection)));
     }
 
@@ -88,7 +610,7 @@ impl<'a> App<'a> {
         self.a_connection = Some(Arc::new(Mutex::new(connection)));
     }
 
-    pub fn get_comm_connection(&mut self) -> &mut Arc<Mutex<TcpStream>> {
+    pub fn get_comm_connection(&mut self) -> &mut Arc<Mutex<OwnedWriteHalf>> {
         self.c_connection.as_mut().unwrap()
     }
 
@@ -96,72 +618,1140 @@ impl<'a> App<'a> {
         self.a_connection.as_mut().unwrap()
     }
 
-    pub fn get_client_song_path(&mut self) -> String {
-        let selected_file = self.client_fs_state.selected().unwrap();
-        let items = get_dir_contents(self.song_dir);
+    /// Re-reads the explored directory, keeping the highlighted entry selected.
+    pub fn refresh_client_entries(&mut self) {
+        let selected_path = self.get_client_entry().map(|entry| entry.path);
 
-        let mut file_path = items.get(selected_file).unwrap().to_string();
+        self.client_entries = self.explorer.list(|entry| {
+            self.probes
+                .get(&entry.path_string())
+                .and_then(|probe| probe.duration)
+        });
+        self.probe_in_background();
+
+        if let Some(watcher) = self.watcher.as_ref() {
+            watcher.acknowledge();
+        }
+        self.rematch(true);
+
+        if let Some(selected) = self.client_fs_state.selected() {
+            let position = selected_path
+                .and_then(|path| {
+                    self.client_entries
+                        .iter()
+                        .position(|entry| entry.path == path)
+                })
+                .and_then(|index| self.view_row(true, index));
+            let last = self.view(true).len().saturating_sub(1);
+            self.client_fs_state
+                .select(Some(position.unwrap_or(selected.min(last))));
+        }
+        self.scan_sync();
+    }
 
-        file_path.insert_str(0, self.song_dir);
+    pub fn get_client_entry(&self) -> Option<Entry> {
+        let selected_file = self.view_index(true, self.client_fs_state.selected()?)?;
+        self.client_entries.get(selected_file).cloned()
+    }
+
+    /// Name shown for a song of the server library:
+    /// "artist - title (length)" when it was uploaded with tags
+    pub fn server_label(&self, song: &str) -> String {
+        match self.state.song_metadata.get(song) {
+            Some(meta) if !meta.title.is_empty() => format!(
+                "{} - {} ({})",
+                meta.artist,
+                meta.title,
+                AudioFile::format_duration(Duration::from_secs(meta.length as u64))
+            ),
+            _ => song.to_string(),
+        }
+    }
+
+    /// Name shown for a local entry: its title tag or file name
+    pub fn client_label(&self, entry: &Entry) -> String {
+        if entry.is_dir {
+            return format!("{}/", entry.name);
+        }
+        self.probes
+            .get(&entry.path_string())
+            .and_then(|probe| probe.title.clone())
+            .unwrap_or_else(|| entry.name.clone())
+    }
+
+    fn labels(&self, client: bool) -> Vec<String> {
+        if client {
+            self.client_entries
+                .iter()
+                .map(|entry| self.client_label(entry))
+                .collect()
+        } else {
+            self.state
+                .song_library
+                .iter()
+                .map(|song| self.server_label(song))
+                .collect()
+        }
+    }
+
+    fn searching(&self, client: bool) -> bool {
+        self.search.as_ref().is_some_and(|search| search.client == client)
+    }
+
+    /// Rows of an explorer as displayed, filtered by the search when it's active
+    /// and, for the server, by the favorites filter.
+    pub fn view(&self, client: bool) -> &[Match] {
+        if client {
+            &self.client_view
+        } else {
+            &self.server_view
+        }
+    }
+
+    /// Rebuilds the rows of [`App::view`] after the entries, the search or
+    /// the favorites filter changed.
+    fn refresh_view(&mut self, client: bool) {
+        let rows: Vec<Match> = match self.search.as_ref() {
+            Some(search) if search.client == client => search.matches.clone(),
+            _ => {
+                let count = if client {
+                    self.client_entries.len()
+                } else {
+                    self.state.song_library.len()
+                };
+                (0..count)
+                    .map(|index| Match {
+                        index,
+                        indices: vec![],
+                    })
+                    .collect()
+            }
+        };
+        if client {
+            self.client_view = rows;
+        } else if self.favorites_only {
+            let library = &self.state.song_library;
+            self.server_view = rows
+                .into_iter()
+                .filter(|row| self.ratings.is_favorite(&library[row.index]))
+                .collect();
+        } else {
+            self.server_view = rows;
+        }
+    }
+
+    /// Displayed row -> index into the unfiltered list
+    fn view_index(&self, client: bool, row: usize) -> Option<usize> {
+        self.view(client).get(row).map(|found| found.index)
+    }
+
+    /// Index into the unfiltered list -> displayed row
+    fn view_row(&self, client: bool, index: usize) -> Option<usize> {
+        self.view(client)
+            .iter()
+            .position(|found| found.index == index)
+    }
+
+    fn fs_state(&mut self, client: bool) -> &mut ListState {
+        if client {
+            &mut self.client_fs_state
+        } else {
+            &mut self.server_fs_state
+        }
+    }
+
+    /// Scores the labels again when they are searched, then updates the view.
+    fn rematch(&mut self, client: bool) {
+        if self.searching(client) {
+            let labels = self.labels(client);
+            if let Some(search) = self.search.as_mut() {
+                search.update(&labels);
+            }
+        }
+        self.refresh_view(client);
+    }
+
+    /// Starts a `/` search over the selected explorer.
+    pub fn start_search(&mut self) {
+        let client = match self.focus {
+            Panel::Server => false,
+            Panel::Client => true,
+            Panel::Queue | Panel::Chat => return,
+        };
+        match self.search.as_mut() {
+            Some(search) if search.client == client => search.editing = true,
+            _ => {
+                self.search = Some(Search::new(client));
+                self.rematch(client);
+                self.fs_state(client).select(Some(0));
+            }
+        }
+    }
+
+    pub fn search_push(&mut self, character: char) {
+        if let Some(search) = self.search.as_mut() {
+            search.query.push(character);
+        }
+        self.search_changed();
+    }
+
+    pub fn search_pop(&mut self) {
+        if let Some(search) = self.search.as_mut() {
+            search.query.pop();
+        }
+        self.search_changed();
+    }
+
+    fn search_changed(&mut self) {
+        let Some(client) = self.search.as_ref().map(|search| search.client) else {
+            return;
+        };
+        self.rematch(client);
+        self.fs_state(client).select(Some(0));
+    }
+
+    /// Leaves the query as is and hands the keys back to the explorer.
+    pub fn stop_search_editing(&mut self) {
+        if let Some(search) = self.search.as_mut() {
+            search.editing = false;
+        }
+    }
+
+    /// Drops the filter, keeping the highlighted item selected.
+    pub fn cancel_search(&mut self) {
+        let Some(search) = self.search.take() else {
+            return;
+        };
+        self.refresh_view(search.client);
+        let state = self.fs_state(search.client);
+        if let Some(row) = state.selected() {
+            state.select(Some(search.original_index(row).unwrap_or(0)));
+        }
+    }
+
+    /// Shows a new directory from its first entry.
+    fn change_dir(&mut self) {
+        if self.searching(true) {
+            self.search = None;
+        }
+        self.client_fs_state.select(Some(0));
+        self.refresh_client_entries();
+        self.client_fs_state.select(Some(0));
+        self.watch_cwd();
+    }
+
+    pub fn enter_dir(&mut self, dir: &Path) {
+        self.explorer.enter(dir);
+        self.change_dir();
+    }
+
+    /// Goes up a directory and keeps the one we came from highlighted.
+    pub fn leave_dir(&mut self) {
+        let Some(left) = self.explorer.leave() else {
+            return;
+        };
+        self.change_dir();
+        let left = left.canonicalize().unwrap_or(left);
+        let position = self
+            .client_entries
+            .iter()
+            .position(|entry| entry.path.canonicalize().is_ok_and(|path| path == left));
+        self.client_fs_state.select(Some(position.unwrap_or(0)));
+    }
+
+    pub fn go_home(&mut self) {
+        self.explorer.go_home();
+        self.change_dir();
+    }
+
+    pub fn go_song_dir(&mut self) {
+        self.explorer.go_song_dir();
+        self.change_dir();
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.explorer.show_hidden = !self.explorer.show_hidden;
+        self.refresh_client_entries();
+    }
+
+    pub fn toggle_audio_only(&mut self) {
+        self.explorer.audio_only = !self.explorer.audio_only;
+        self.refresh_client_entries();
+    }
+
+    pub fn cycle_sort(&mut self) {
+        self.explorer.sort = self.explorer.sort.next();
+        self.refresh_client_entries();
+    }
+
+    /// Probes a local file, reusing the last result while the file is unchanged.
+    pub fn probe_client_song(&mut self, file_path: &str) -> &ProbeInfo {
+        let modified = AudioFile::modified(file_path);
+        self.probe_cached(file_path, modified)
+    }
+
+    /// Last probe of a listed file, never blocks: changed files are probed
+    /// again in the background and new ones show [`AudioFile::PENDING`] until then.
+    pub fn probe_entry(&self, entry: &Entry) -> &ProbeInfo {
+        if !entry.is_audio() {
+            return &AudioFile::NOT_AUDIO;
+        }
+        self.probes
+            .get(&entry.path_string())
+            .unwrap_or(&AudioFile::PENDING)
+    }
+
+    /// Probes the listed audio files whose probe is missing or outdated,
+    /// off the UI thread. Results arrive as [`Event::Probed`].
+    fn probe_in_background(&mut self) {
+        let Some(sender) = self.events.clone() else {
+            return;
+        };
+        let stale: Vec<String> = self
+            .client_entries
+            .iter()
+            .filter(|entry| !entry.is_dir && entry.is_audio())
+            .filter(|entry| {
+                self.probes
+                    .get(&entry.path_string())
+                    .is_none_or(|probe| probe.modified != entry.modified)
+            })
+            .map(Entry::path_string)
+            .filter(|path| !self.probing.contains(path))
+            .collect();
+        if stale.is_empty() {
+            return;
+        }
+        self.probing.extend(stale.iter().cloned());
+        tokio::task::spawn_blocking(move || {
+            // A few at a time, so a large directory fills in gradually
+            for paths in stale.chunks(PROBE_BATCH) {
+                let probes = paths
+                    .iter()
+                    .map(|path| (path.clone(), AudioFile::probe(path)))
+                    .collect();
+                if sender.send(Event::Probed(probes)).is_err() {
+                    return;
+                }
+            }
+        });
+    }
+
+    /// Takes in background probe results.
+    pub fn add_probes(&mut self, probes: Vec<(String, ProbeInfo)>) {
+        for (path, probe) in probes {
+            self.probing.remove(&path);
+            self.probes.insert(path, probe);
+        }
+        self.index_dirty = true;
+        // Durations decide the order, titles what a search matches
+        if self.explorer.sort == SortKey::Duration {
+            self.refresh_client_entries();
+        } else {
+            self.rematch(true);
+        }
+    }
+
+    fn probe_cached(&mut self, file_path: &str, modified: Option<SystemTime>) -> &ProbeInfo {
+        // Other files are never opened, they may be anything
+        if !AudioFile::is_audio(Path::new(file_path)) {
+            return &AudioFile::NOT_AUDIO;
+        }
+        let stale = self
+            .probes
+            .get(file_path)
+            .is_none_or(|probe| probe.modified != modified);
+        if stale {
+            self.probes
+                .insert(file_path.to_string(), AudioFile::probe(file_path));
+            self.index_dirty = true;
+        }
+        &self.probes[file_path]
+    }
 
-        file_path
+    pub fn toggle_transcode(&mut self) {
+        self.transcode_uploads = !self.transcode_uploads;
     }
 
-    pub fn get_song(&mut self) -> String {
-        let selected_song = self.server_fs_state.selected().unwrap();
+    pub fn get_song(&mut self) -> Option<String> {
+        let selected_song = self.view_index(false, self.server_fs_state.selected()?)?;
 
-        self.state.song_library[selected_song].clone()
+        self.state.song_library.get(selected_song).cloned()
     }
 
     /// Handles the tick event of the terminal.
-    pub fn tick(&self) {}
+    pub fn tick(&mut self) {
+        if self.index_dirty {
+            if let Err(err) = AudioFile::save_index(&self.index_path(), &self.probes) {
+                self.notify(
+                    Severity::Error,
+                    format!("Can't save the library index: {}", err),
+                );
+            }
+            self.index_dirty = false;
+        }
+        for burst in self.bursts.iter_mut() {
+            burst.age += 1;
+        }
+        self.bursts.retain(|burst| burst.age < BURST_TICKS);
+        for toast in self.toasts.iter_mut().take(TOAST_LIMIT) {
+            toast.age += 1;
+        }
+        self.toasts.retain(|toast| toast.age < TOAST_TICKS);
+    }
+
+    /// Animates reactions of others, our own only show up in the counts.
+    pub fn push_reaction(&mut self, reaction: Reaction) {
+        if reaction.from != self.nickname {
+            self.bursts.push(Burst { reaction, age: 0 });
+        }
+    }
 
     /// Set running to false to quit the application.
     pub fn quit(&mut self) {
         self.running.store(false, Ordering::Relaxed);
-        println!("app detected quit");
-        if let Some(conn) = self.c_connection.take() {
-            let _ = conn.lock().unwrap().shutdown();
-            println!("C_connection shutdown");
+        // Whatever was on air counts as heard
+        self.finish_song();
+        // Dropping the write half shuts the connection down for the server
+        if self.c_connection.take().is_some() {
+            self.notify(Severity::Info, "Disconnected from the server".to_string());
+        }
+    }
+
+    fn focused_state(&mut self) -> Option<&mut ListState> {
+        match self.focus {
+            Panel::Server => Some(&mut self.server_fs_state),
+            Panel::Client => Some(&mut self.client_fs_state),
+            Panel::Queue => Some(&mut self.queue_state),
+            Panel::Chat => None,
         }
     }
 
     pub fn handle_fs_state(&mut self, direction: &str) {
-        if self.client_fs_selected {
-            if direction == "down" {
-                self.client_fs_state.select_next();
-            } else {
-                self.client_fs_state.select_previous();
-            }
+        let Some(state) = self.focused_state() else {
+            return;
+        };
+        if direction == "down" {
+            state.select_next();
         } else {
-            if direction == "down" {
-                self.server_fs_state.select_next();
+            state.select_previous();
+        }
+    }
+
+    pub fn toggle_help(&mut self) {
+        self.help_scroll = match self.help_scroll {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    /// Scrolls the help overlay, rendering keeps it within the list.
+    pub fn scroll_help(&mut self, up: bool, lines: usize) {
+        if let Some(scroll) = self.help_scroll.as_mut() {
+            *scroll = if up {
+                scroll.saturating_sub(lines)
+            } else {
+                scroll.saturating_add(lines)
+            };
+        }
+    }
+
+    pub fn toggle_log(&mut self) {
+        self.log_scroll = match self.log_scroll {
+            Some(_) => None,
+            None => Some(0),
+        };
+    }
+
+    /// Scrolls the log viewer back from the newest entry, rendering keeps it
+    /// within the log.
+    pub fn scroll_log(&mut self, up: bool, lines: usize) {
+        if let Some(scroll) = self.log_scroll.as_mut() {
+            *scroll = if up {
+                scroll.saturating_add(lines)
             } else {
-                self.server_fs_state.select_previous();
+                scroll.saturating_sub(lines)
+            };
+        }
+    }
+
+    /// Number of rows a list panel shows.
+    fn panel_rows(&self, panel: Panel) -> usize {
+        match panel {
+            Panel::Server => self.view(false).len(),
+            Panel::Client => self.view(true).len(),
+            Panel::Queue => self.state.song_queue.len(),
+            Panel::Chat => 0,
+        }
+    }
+
+    /// Jumps to the first or last row of the focused panel.
+    pub fn select_edge(&mut self, top: bool) {
+        let rows = self.panel_rows(self.focus);
+        let row = if top { 0 } else { rows.saturating_sub(1) };
+        if let Some(state) = self.focused_state() {
+            state.select(Some(row));
+        }
+    }
+
+    /// Moves the focus to the next or previous panel in tab order.
+    pub fn cycle_focus(&mut self, forward: bool) {
+        let panel = if forward {
+            self.focus.next()
+        } else {
+            self.focus.previous()
+        };
+        self.focus_panel(panel);
+    }
+
+    /// Moves the keyboard focus. Panels keep their selection when they lose
+    /// the focus, one focused for the first time starts at the top.
+    pub fn focus_panel(&mut self, panel: Panel) {
+        self.cancel_search();
+        self.focus = panel;
+        if let Some(state) = self.focused_state() {
+            if state.selected().is_none() {
+                state.select(Some(0));
             }
         }
     }
 
-    pub fn switch_fs(&mut self) {
-        self.client_fs_selected = !self.client_fs_selected;
-        if self.client_fs_selected {
-            *self.server_fs_state.selected_mut() = None;
-            *self.client_fs_state.selected_mut() = Some(0);
+    /// Panel under a screen cell.
+    pub fn panel_at(&self, position: Position) -> Option<Panel> {
+        let areas = self.areas;
+        if areas.server.contains(position) {
+            Some(Panel::Server)
+        } else if areas.client.contains(position) {
+            Some(Panel::Client)
+        } else if areas.queue.contains(position) {
+            Some(Panel::Queue)
+        } else if areas.chat.contains(position) || areas.chat_input.contains(position) {
+            Some(Panel::Chat)
         } else {
-            *self.server_fs_state.selected_mut() = Some(0);
-            *self.client_fs_state.selected_mut() = None;
+            None
         }
     }
 
-    pub fn update_state(&mut self, state: ServerState) {
-        self.state = state;
+    /// Selects the row of a list panel under a screen cell, focusing the panel.
+    /// False when the cell holds no row.
+    pub fn select_at(&mut self, panel: Panel, position: Position) -> bool {
+        if self.focus != panel {
+            self.focus_panel(panel);
+        }
+        let area = match panel {
+            Panel::Server => self.areas.server,
+            Panel::Client => self.areas.client,
+            Panel::Queue => self.areas.queue,
+            Panel::Chat => return false,
+        };
+        let rows = self.panel_rows(panel);
+        let Some(state) = self.focused_state() else {
+            return false;
+        };
+        // Below the top border, counting the rows scrolled out of view
+        let row = (position.y.saturating_sub(area.y + 1)) as usize + state.offset();
+        if position.y <= area.y || row >= rows {
+            return false;
+        }
+        state.select(Some(row));
+        true
     }
 
-    pub fn handle_fs_actions(&mut self) {
-        if self.client_fs_selected {
-            println!("Send file to server");
+    /// Whether a click at `position` completes a double click.
+    pub fn double_click(&mut self, position: Position) -> bool {
+        let now = Instant::now();
+        let double = self.last_click.is_some_and(|(time, last)| {
+            last.y == position.y
+                && self.panel_at(last) == self.panel_at(position)
+                && now.duration_since(time) < Duration::from_millis(400)
+        });
+        // A third click starts over
+        self.last_click = if double { None } else { Some((now, position)) };
+        double
+    }
+
+    /// Picks the layout for a new terminal size.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.compact = width < COMPACT_WIDTH || height < COMPACT_HEIGHT;
+    }
+
+    pub fn toggle_zoom(&mut self) {
+        self.zoomed = !self.zoomed;
+    }
+
+    /// Panel tab under a screen cell, in the compact layout.
+    pub fn tab_at(&self, position: Position) -> Option<Panel> {
+        Panel::ALL
+            .into_iter()
+            .zip(self.areas.tabs)
+            .find(|(_, area)| area.contains(position))
+            .map(|(panel, _)| panel)
+    }
+
+    pub fn toggle_position(&mut self) {
+        self.show_position = !self.show_position;
+    }
+
+    /// Time since the song on air started, as far as we saw it, and its length when tagged.
+    pub fn song_position(&self) -> Option<(Duration, Option<Duration>)> {
+        let entry = self.now_playing.as_ref()?;
+        let elapsed = (Local::now().timestamp() - entry.started).max(0) as u64;
+        let length = self
+            .state
+            .song_metadata
+            .get(&entry.song)
+            .filter(|meta| meta.length > 0)
+            .map(|meta| Duration::from_secs(meta.length as u64));
+        Some((Duration::from_secs(elapsed), length))
+    }
+
+    pub fn push_chat(&mut self, message: ChatMessage) {
+        self.chat.push_back(message);
+        if self.chat.len() > CHAT_HISTORY {
+            self.chat.pop_front();
+        }
+        // Someone reading the scrollback keeps looking at the same lines
+        if self.chat_scroll > 0 {
+            self.chat_scroll = (self.chat_scroll + 1).min(self.chat.len().saturating_sub(1));
+        }
+    }
+
+    /// Space on a row: marks or unmarks it and moves on to the next one.
+    pub fn toggle_mark(&mut self) {
+        match self.focus {
+            Panel::Client => {
+                // Directories can't be uploaded
+                let Some(entry) = self.get_client_entry().filter(|entry| !entry.is_dir) else {
+                    return;
+                };
+                if !self.client_marks.remove(&entry.path) {
+                    self.client_marks.insert(entry.path);
+                }
+            }
+            Panel::Server => {
+                let Some(song) = self.get_song() else {
+                    return;
+                };
+                if !self.server_marks.remove(&song) {
+                    self.server_marks.insert(song);
+                }
+            }
+            Panel::Queue | Panel::Chat => return,
+        }
+        self.handle_fs_state("down");
+    }
+
+    /// Marks every row the focused explorer shows.
+    pub fn mark_all(&mut self) {
+        match self.focus {
+            Panel::Client => {
+                let paths: Vec<PathBuf> = self
+                    .view(true)
+                    .iter()
+                    .map(|row| &self.client_entries[row.index])
+                    .filter(|entry| !entry.is_dir)
+                    .map(|entry| entry.path.clone())
+                    .collect();
+                self.client_marks.extend(paths);
+            }
+            Panel::Server => {
+                let songs: Vec<String> = self
+                    .view(false)
+                    .iter()
+                    .map(|row| self.state.song_library[row.index].clone())
+                    .collect();
+                self.server_marks.extend(songs);
+            }
+            Panel::Queue | Panel::Chat => {}
+        }
+    }
+
+    pub fn clear_marks(&mut self) {
+        match self.focus {
+            Panel::Client => self.client_marks.clear(),
+            Panel::Server => self.server_marks.clear(),
+            Panel::Queue | Panel::Chat => {}
+        }
+    }
+
+    /// Marked server songs in library order, clearing the marks.
+    pub fn take_server_marks(&mut self) -> Vec<String> {
+        let marks = std::mem::take(&mut self.server_marks);
+        self.state
+            .song_library
+            .iter()
+            .filter(|song| marks.contains(*song))
+            .cloned()
+            .collect()
+    }
+
+    fn save_ratings(&mut self) {
+        if let Err(err) = Ratings::save(&self.ratings_path(), &self.server, &self.ratings) {
+            self.notify(Severity::Error, format!("Can't save ratings: {}", err));
+        }
+    }
+
+    pub fn toggle_favorite(&mut self) {
+        let Some(song) = self.get_song() else {
+            return;
+        };
+        self.ratings.toggle_favorite(&song);
+        self.save_ratings();
+        // Unstarring in the favorites view drops the row
+        if self.favorites_only {
+            self.refresh_view(false);
+            self.keep_server_selection_in_view();
+        }
+    }
+
+    pub fn cycle_stars(&mut self) {
+        if let Some(song) = self.get_song() {
+            self.ratings.cycle_stars(&song);
+            self.save_ratings();
+        }
+    }
+
+    pub fn toggle_favorites_only(&mut self) {
+        self.favorites_only = !self.favorites_only;
+        self.refresh_view(false);
+        self.server_fs_state.select(Some(0));
+    }
+
+    fn keep_server_selection_in_view(&mut self) {
+        let last = self.view(false).len().saturating_sub(1);
+        if let Some(selected) = self.server_fs_state.selected() {
+            self.server_fs_state.select(Some(selected.min(last)));
+        }
+    }
+
+    /// Any favorite still in the library, None when there is none.
+    pub fn random_favorite(&self) -> Option<String> {
+        let favorites: Vec<&String> = self
+            .state
+            .song_library
+            .iter()
+            .filter(|song| self.ratings.is_favorite(song))
+            .collect();
+        if favorites.is_empty() {
+            return None;
+        }
+        // Clock nanoseconds are random enough for picking a song
+        let seed = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |now| now.subsec_nanos() as usize);
+        Some(favorites[seed % favorites.len()].clone())
+    }
+
+    /// Queues local files for upload, on top of a running batch.
+    pub fn start_upload_batch(&mut self, paths: Vec<String>) {
+        let batch = self.upload_batch.get_or_insert_with(Batch::default);
+        batch.total += paths.len();
+        batch.pending.extend(paths);
+    }
+
+    /// Resolves playlist entries against the server library by name, then
+    /// by content, and finds local files for the rest.
+    pub fn resolve_playlist(&mut self, path: &Path) -> std::io::Result<PlaylistImport> {
+        let server_by_hash: HashMap<String, String> = self
+            .state
+            .song_hashes
+            .iter()
+            .map(|(name, hash)| (hash.clone(), name.clone()))
+            .collect();
+        let mut import = PlaylistImport {
+            name: path
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().to_string()),
+            songs: vec![],
+            missing: vec![],
+            unresolved: 0,
+        };
+        for location in Playlist::read(path)? {
+            let name = location
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().to_string());
+            if self.state.song_library.contains(&name) {
+                import.songs.push(name);
+                continue;
+            }
+            if !location.is_file() {
+                import.unresolved += 1;
+                continue;
+            }
+            if let Some(song) = hash_file(&location)
+                .ok()
+                .and_then(|hash| server_by_hash.get(&hash))
+            {
+                import.songs.push(song.clone());
+                continue;
+            }
+            // Name the song will have in the library once uploaded
+            let location_string = location.to_string_lossy().to_string();
+            let support = self.probe_client_song(&location_string).support;
+            let name = match support {
+                Support::Unsupported => {
+                    import.unresolved += 1;
+                    continue;
+                }
+                Support::Transcode if self.transcode_uploads => Path::new(&name)
+                    .with_extension("wav")
+                    .to_string_lossy()
+                    .to_string(),
+                _ => name,
+            };
+            import.songs.push(name.clone());
+            import.missing.push((location, name));
+        }
+        Ok(import)
+    }
+
+    fn playlist_entry(&self, song: &str, title: String) -> PlaylistEntry {
+        PlaylistEntry {
+            location: song.to_string(),
+            title,
+            length: self
+                .state
+                .song_metadata
+                .get(song)
+                .filter(|meta| meta.length > 0)
+                .map_or(-1, |meta| meta.length as i64),
+        }
+    }
+
+    /// Writes songs as an M3U file into the song directory, named after
+    /// what they are and the current time.
+    fn export_m3u(&mut self, what: &str, entries: Vec<PlaylistEntry>) {
+        let name = format!("{}-{}.m3u", what, Local::now().format("%Y%m%d-%H%M%S"));
+        let path = Path::new(self.song_dir).join(name);
+        match Playlist::write_m3u(&path, &entries) {
+            Ok(()) => self.notify(
+                Severity::Info,
+                format!("Saved the {} to {}", what, path.display()),
+            ),
+            Err(err) => self.notify(Severity::Error, format!("Can't save the {}: {}", what, err)),
+        }
+    }
+
+    pub fn export_queue(&mut self) {
+        let entries = self
+            .state
+            .song_queue
+            .iter()
+            .map(|song| self.playlist_entry(song, self.server_label(song)))
+            .collect();
+        self.export_m3u("queue", entries);
+    }
+
+    /// Exports the songs the history view shows, in the order they played.
+    pub fn export_history(&mut self) {
+        let Some(view) = self.history_view.as_ref() else {
+            return;
+        };
+        let mut rows: Vec<usize> = view.matches.iter().map(|row| row.index).collect();
+        rows.sort_unstable_by(|a, b| b.cmp(a));
+        let entries = rows
+            .into_iter()
+            .map(|row| &self.history[self.history.len() - 1 - row])
+            .map(|entry| self.playlist_entry(&entry.song, entry.title.clone()))
+            .collect();
+        self.export_m3u("history", entries);
+    }
+
+    /// Follows the front of the queue, the song that leaves it goes to the history.
+    fn track_history(&mut self) {
+        let on_air = self.state.song_queue.first().cloned();
+        if self.now_playing.as_ref().map(|entry| &entry.song) != on_air.as_ref() {
+            self.finish_song();
+            self.now_playing = on_air.map(|song| HistoryEntry {
+                title: self.server_label(&song),
+                started: Local::now().timestamp(),
+                queued_by: self.state.queued_by.first().cloned().unwrap_or_default(),
+                reactions: vec![],
+                song,
+            });
+        }
+        if let Some(entry) = self.now_playing.as_mut() {
+            entry.reactions = self.state.reactions.clone();
+        }
+    }
+
+    fn finish_song(&mut self) {
+        let Some(entry) = self.now_playing.take() else {
+            return;
+        };
+        if let Err(err) = History::append(&self.history_path(), &entry) {
+            self.notify(
+                Severity::Error,
+                format!("Can't save the play history: {}", err),
+            );
+        }
+        self.history.push(entry);
+        self.rematch_history();
+    }
+
+    pub fn toggle_history(&mut self) {
+        if self.history_view.take().is_none() {
+            self.history_view = Some(HistoryView::default());
+            self.rematch_history();
+        }
+    }
+
+    /// Searchable text of a history row, newest first.
+    pub fn history_labels(&self) -> Vec<String> {
+        self.history
+            .iter()
+            .rev()
+            .map(|entry| {
+                let started = Local
+                    .timestamp_opt(entry.started, 0)
+                    .single()
+                    .map_or(String::new(), |time| time.format("%d.%m %H:%M").to_string());
+                format!("{}  {}  by {}", started, entry.title, entry.queued_by)
+            })
+            .collect()
+    }
+
+    fn rematch_history(&mut self) {
+        let labels = self.history_labels();
+        let Some(view) = self.history_view.as_mut() else {
+            return;
+        };
+        view.matches = fuzzy_match(&labels, &view.query);
+        let selected = view.state.selected().unwrap_or(0);
+        view.state
+            .select(Some(selected.min(view.matches.len().saturating_sub(1))));
+    }
+
+    pub fn history_query_push(&mut self, character: char) {
+        if let Some(view) = self.history_view.as_mut() {
+            view.query.push(character);
+            view.state.select(Some(0));
+        }
+        self.rematch_history();
+    }
+
+    pub fn history_query_pop(&mut self) {
+        if let Some(view) = self.history_view.as_mut() {
+            view.query.pop();
+        }
+        self.rematch_history();
+    }
+
+    pub fn clear_history_query(&mut self) {
+        if let Some(view) = self.history_view.as_mut() {
+            view.query.clear();
+        }
+        self.rematch_history();
+    }
+
+    /// History entry of the highlighted row.
+    pub fn history_entry(&self) -> Option<HistoryEntry> {
+        let view = self.history_view.as_ref()?;
+        let row = view.matches.get(view.state.selected()?)?;
+        self.history
+            .get(self.history.len() - 1 - row.index)
+            .cloned()
+    }
+
+    pub fn toggle_sync(&mut self) {
+        if self.sync.take().is_none() {
+            self.sync = Some(SyncView::default());
+            self.scan_sync();
+        }
+    }
+
+    /// Hashes the song directory for an open sync view, off the UI thread.
+    /// The result arrives as [`Event::SyncScanned`].
+    fn scan_sync(&mut self) {
+        let (Some(sync), Some(sender)) = (self.sync.as_mut(), self.events.clone()) else {
+            return;
+        };
+        sync.scanning = true;
+        // One scan at a time, another follows if this one may be outdated
+        let Some(mut hashes) = self.local_hashes.take() else {
+            self.rescan_sync = true;
+            return;
+        };
+        let dir = PathBuf::from(self.song_dir);
+        tokio::task::spawn_blocking(move || {
+            let songs = hashes.scan(&dir);
+            let _ = sender.send(Event::SyncScanned(hashes, songs));
+        });
+    }
+
+    /// Takes in a background scan of the song directory.
+    pub fn add_sync_scan(&mut self, hashes: LocalHashes, songs: Vec<LocalSong>) {
+        self.local_hashes = Some(hashes);
+        self.local_songs = songs;
+        if std::mem::take(&mut self.rescan_sync) {
+            self.scan_sync();
+        } else if let Some(sync) = self.sync.as_mut() {
+            sync.scanning = false;
+        }
+        self.refresh_sync();
+    }
+
+    /// Re-diffs an open sync view against the latest scan, keeping the
+    /// selected song.
+    pub fn refresh_sync(&mut self) {
+        if self.sync.is_none() {
+            return;
+        }
+        let entries = diff(
+            &self.local_songs,
+            &self.state.song_library,
+            &self.state.song_hashes,
+        );
+        let Some(sync) = self.sync.as_mut() else {
+            return;
+        };
+        let selected = sync
+            .state
+            .selected()
+            .and_then(|index| sync.entries.get(index))
+            .map(|entry| entry.name.clone());
+        let position = selected
+            .and_then(|name| entries.iter().position(|entry| entry.name == name))
+            .unwrap_or(0);
+        sync.entries = entries;
+        sync.state.select(Some(position));
+    }
+
+    pub fn sync_entry(&self) -> Option<SyncEntry> {
+        let sync = self.sync.as_ref()?;
+        sync.entries.get(sync.state.selected()?).cloned()
+    }
+
+    /// Songs of the sync view with the given status.
+    pub fn sync_entries(&self, status: SyncStatus) -> Vec<SyncEntry> {
+        self.sync.as_ref().map_or(vec![], |sync| {
+            sync.entries
+                .iter()
+                .filter(|entry| entry.status == status)
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Queues a toast, the status bar shows it right away.
+    pub fn notify(&mut self, severity: Severity, text: String) {
+        match severity {
+            Severity::Info => tracing::info!("{}", text),
+            Severity::Warning => tracing::warn!("{}", text),
+            Severity::Error => tracing::error!("{}", text),
+        }
+        let notification = Notification {
+            severity,
+            text,
+            age: 0,
+        };
+        self.status = Some(notification.clone());
+        self.toasts.push_back(notification);
+    }
+
+    pub fn start_download(&mut self, info: DownloadInfo) {
+        if let Some(error) = info.error {
+            self.notify(
+                Severity::Error,
+                format!("Can't download {}: {}", info.name, error),
+            );
+            return;
+        }
+        match Download::start(Path::new(self.song_dir), &info.name, info.size, &info.hash) {
+            Ok(download) => {
+                // An empty song has no chunks coming
+                self.downloads.insert(info.name.clone(), download);
+                self.finish_download(&info.name);
+            }
+            Err(err) => self.notify(
+                Severity::Error,
+                format!("Can't download {}: {}", info.name, err),
+            ),
+        }
+    }
+
+    pub fn download_chunk(&mut self, chunk: DownloadChunk) {
+        let Some(download) = self.downloads.get_mut(&chunk.name) else {
+            return;
+        };
+        let written = BASE64
+            .decode(&chunk.data)
+            .map_err(|err| err.to_string())
+            .and_then(|data| download.write(&data).map_err(|err| err.to_string()));
+        if let Err(err) = written {
+            if let Some(download) = self.downloads.remove(&chunk.name) {
+                download.abort();
+            }
+            self.notify(
+                Severity::Error,
+                format!("Download of {} failed: {}", chunk.name, err),
+            );
+            return;
+        }
+        self.finish_download(&chunk.name);
+    }
+
+    fn finish_download(&mut self, name: &str) {
+        let complete = self.downloads.get(name).is_some_and(Download::is_complete);
+        if !complete {
+            return;
+        }
+        let Some(download) = self.downloads.remove(name) else {
+            return;
+        };
+        let (severity, message) = match download.finish() {
+            Ok(Outcome::Saved(path)) => (Severity::Info, format!("Downloaded {}", path.display())),
+            Ok(Outcome::AlreadyHave(path)) => {
+                (Severity::Info, format!("Already have {}", path.display()))
+            }
+            Ok(Outcome::Corrupted) => (
+                Severity::Error,
+                format!("Download of {} failed verification", name),
+            ),
+            Err(err) => (
+                Severity::Error,
+                format!("Download of {} failed: {}", name, err),
+            ),
+        };
+        self.notify(severity, message);
+        self.refresh_client_entries();
+    }
+
+    pub fn scroll_chat(&mut self, up: bool, lines: usize) {
+        self.chat_scroll = if up {
+            (self.chat_scroll + lines).min(self.chat.len().saturating_sub(1))
         } else {
-            println!("Add song to server playback queue");
+            self.chat_scroll.saturating_sub(lines)
+        };
+    }
+
+    /// Queue position of the highlighted song
+    pub fn get_queue_index(&self) -> Option<usize> {
+        self.queue_state
+            .selected()
+            .filter(|&selected| selected < self.state.song_queue.len())
+    }
+
+    pub fn update_state(&mut self, state: ServerState) {
+        let selected_song = self.get_song();
+        self.state = state;
+        self.track_history();
+        self.rematch(false);
+        self.refresh_sync();
+
+        // Keep the same song highlighted when the library changes around it
+        if self.server_fs_state.selected().is_some() {
+            let position = selected_song
+                .and_then(|song| self.state.song_library.iter().position(|name| *name == song))
+                .and_then(|index| self.view_row(false, index));
+            if let Some(position) = position {
+                self.server_fs_state.select(Some(position));
+            }
         }
     }
+
+    pub fn update_codec(&mut self, handshake: Handshake) {
+        self.bitrate = handshake.bitrate;
+        self.codec = handshake.codec;
+    }
 }
@@ -1,12 +1,16 @@
 use std::time::Duration;
-use std::sync::{Arc, Mutex};
 use crossterm::event::{Event as CrosstermEvent, KeyEvent, MouseEvent};
-use crossterm::event::KeyCode;
 use futures::{FutureExt, StreamExt};
-use tokio::net::TcpStream;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::tcp::OwnedReadHalf;
 use tokio::sync::mpsc;
 
-use crate::app::AppResult;
+use crate::app::{AppResult, Severity};
+use crate::lib::{
+    AudioFile::ProbeInfo,
+    Sync::{LocalHashes, LocalSong},
+};
+use tracing::{debug, info, trace};
 
 /// Terminal events.
 #[derive(Clone, Debug)]
@@ -19,10 +23,17 @@ pub enum Event {
     Mouse(MouseEvent),
     /// Terminal resize.
     Resize(u16, u16),
-    /// Server update network communication
-    Net([u8; 1024]),
-    /// File Transfer 
-    FileTransfer,
+    /// Server update network communication (one newline delimited message)
+    Net(String),
+    /// Contents of the directory shown in the local explorer changed
+    LocalLibraryChanged,
+    /// Message for the user from a background task
+    Notify(Severity, String),
+    /// Local files probed in the background, keyed by path
+    Probed(Vec<(String, ProbeInfo)>),
+    /// Song directory hashed in the background for the sync view, with the
+    /// hashes handed back for the next scan
+    SyncScanned(LocalHashes, Vec<LocalSong>),
 }
 
 /// Terminal event handler.
@@ -39,17 +50,21 @@ pub struct EventHandler {
 
 impl EventHandler {
     /// Constructs a new instance of [`EventHandler`].
-    pub fn new(tick_rate: u64, stream: Arc<Mutex<TcpStream>>) -> Self {
+    pub fn new(tick_rate: u64, stream: OwnedReadHalf) -> Self {
         let tick_rate = Duration::from_millis(tick_rate);
         let (sender, receiver) = mpsc::unbounded_channel();
         let _sender = sender.clone();
         let handler = tokio::spawn(async move {
+            info!(?tick_rate, "event handler started");
             let mut reader = crossterm::event::EventStream::new();
             let mut tick = tokio::time::interval(tick_rate);
+            let mut net_reader = BufReader::new(stream);
+            let mut net_open = true;
+            // Partial reads stay here until the whole line has arrived
+            let mut update_buf: Vec<u8> = Vec::new();
             loop {
                 let tick_delay = tick.tick();
                 let crossterm_event = reader.next().fuse();
-                let mut update_buf = [0; 1024];
                 tokio::select! {
                   _ = _sender.closed() => {
                     break;
@@ -57,23 +72,27 @@ impl EventHandler {
                   _ = tick_delay => {
                     _sender.send(Event::Tick).unwrap();
                   }
-                result = async { stream.lock().unwrap().try_read(&mut update_buf) } => {
+                result = net_reader.read_until(b'\n', &mut update_buf), if net_open => {
                     match result {
                             Ok(0) => {
                                 // connection closed
-                                continue;
+                                info!("server closed the connection");
+                                net_open = false;
                             }
                             Ok(_n) => {
-                                let data = update_buf;
-                                let _ = _sender.send(Event::Net(data));
-                                // If n == 1024 check for rest of message if neccessary
-                            }
-                            Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                                // no new data in socket
-                                continue;
+                                let message = String::from_utf8_lossy(&update_buf).trim().to_string();
+                                update_buf.clear();
+                                if !message.is_empty() {
+                                    trace!(bytes = message.len(), "server message");
+                                    let _ = _sender.send(Event::Net(message));
+                                }
                             }
                             Err(err) => {
-                            eprintln!("Failed to read from stream: {}", err);
+                            let _ = _sender.send(Event::Notify(
+                                Severity::Error,
+                                format!("Can't read from the server: {}", err),
+                            ));
+                            net_open = false;
                             }
                         }
                 }
@@ -82,17 +101,14 @@ impl EventHandler {
                     match evt {
                       CrosstermEvent::Key(key) => {
                         if key.kind == crossterm::event::KeyEventKind::Press {
-                            if key.code == KeyCode::Enter {
-                                _sender.send(Event::FileTransfer).unwrap();
-                            } else {
-                                _sender.send(Event::Key(key)).unwrap();
-                            } 
+                            _sender.send(Event::Key(key)).unwrap();
                         }
                       },
                       CrosstermEvent::Mouse(mouse) => {
                         _sender.send(Event::Mouse(mouse)).unwrap();
                       },
                       CrosstermEvent::Resize(x, y) => {
+                        debug!(x, y, "terminal resized");
                         _sender.send(Event::Resize(x, y)).unwrap();
                       },
                       CrosstermEvent::FocusLost => {
@@ -113,6 +129,11 @@ impl EventHandler {
         }
     }
 
+    /// Sender for events produced outside the handler (e.g. the library watcher).
+    pub fn sender(&self) -> mpsc::UnboundedSender<Event> {
+        self.sender.clone()
+    }
+
     /// Receive the next event from the handler thread.
     ///
     /// This function will always block the current thread if
@@ -121,9 +142,6 @@ impl EventHandler {
         self.receiver
             .recv()
             .await
-            .ok_or(Box::new(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "This is an IO error",
-            )))
+            .ok_or(Box::new(std::io::Error::other("This is an IO error")))
     }
 }
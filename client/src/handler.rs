@@ -1,65 +1,721 @@
 use crate::{
-    app::{App, AppResult, ServerState},
-    lib::NetUtils::{sendSong, toQueue},
+    app::{App, AppResult, Panel, ServerMessage, Severity},
+    lib::{
+        AudioFile::{transcode, Support},
+        Keymap::{Action, Key, Lookup},
+        NetUtils::{
+            editQueue, requestDownload, sendChat, sendMetadata, sendReaction, sendSong,
+            sendSongData, sendVote, server_song_path, toQueue, QueueEdit, Vote,
+        },
+        Sync::SyncStatus,
+    },
 };
-use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Position;
+use std::path::Path;
+
+/// Sends a queue edit, a failed send is reported instead of ending the session.
+async fn edit_queue(edit: QueueEdit, index: usize, app: &mut App<'_>) {
+    if let Err(err) = editQueue(edit, index as u32, app).await {
+        app.notify(Severity::Error, format!("Can't edit the queue: {}", err));
+    }
+}
+
+/// Sends a vote, a failed send is reported instead of ending the session.
+async fn vote(vote: Vote, index: usize, app: &mut App<'_>) {
+    if let Err(err) = sendVote(vote, index as u32, app).await {
+        app.notify(Severity::Error, format!("Can't send the vote: {}", err));
+    }
+}
+
+/// Sends a queue edit for the highlighted queue entry.
+async fn edit_selected(edit: QueueEdit, app: &mut App<'_>) {
+    if let Some(index) = app.get_queue_index() {
+        edit_queue(edit, index, app).await;
+    }
+}
 
 /// Handles the key events and updates the state of [`App`].
-pub fn handle_key_events(key_event: KeyEvent, app: &mut App) -> AppResult<()> {
-    match key_event.code {
-        // Exit application on `ESC` or `q`
-        KeyCode::Esc | KeyCode::Char('q') => {
-            app.quit();
+pub async fn handle_key_events(key_event: KeyEvent, app: &mut App<'_>) -> AppResult<()> {
+    let ctrl_c = matches!(key_event.code, KeyCode::Char('c') | KeyCode::Char('C'))
+        && key_event.modifiers == KeyModifiers::CONTROL;
+    let action = app.keymap.action(Key::from(key_event));
+
+    // While typing a search query keys go to the query
+    if app.search.as_ref().is_some_and(|search| search.editing) {
+        match key_event.code {
+            _ if ctrl_c => app.quit(),
+            KeyCode::Esc => app.cancel_search(),
+            KeyCode::Enter => handle_file_actions(app).await?,
+            KeyCode::Backspace => app.search_pop(),
+            KeyCode::Up => app.handle_fs_state("up"),
+            KeyCode::Down => app.handle_fs_state("down"),
+            KeyCode::Char(character) => app.search_push(character),
+            _ => {}
         }
-        // Exit application on `Ctrl-C`
-        KeyCode::Char('c') | KeyCode::Char('C') => {
-            if key_event.modifiers == KeyModifiers::CONTROL {
-                app.quit();
+        return Ok(());
+    }
+
+    // A playlist import waits for an answer about missing songs
+    if app.playlist_import.is_some() {
+        match (app.keymap.answer(Key::from(key_event)), action) {
+            _ if ctrl_c => app.quit(),
+            (Some(Action::Confirm), _) => finish_import(true, app).await,
+            (Some(Action::Decline), _) => finish_import(false, app).await,
+            (_, Some(Action::Cancel)) => app.playlist_import = None,
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    // The queue is everyone's, one stray key shouldn't wipe it
+    if app.confirm_clear_queue {
+        match (app.keymap.answer(Key::from(key_event)), action) {
+            _ if ctrl_c => app.quit(),
+            (Some(Action::Confirm), _) => {
+                app.confirm_clear_queue = false;
+                edit_queue(QueueEdit::Clear, 0, app).await;
             }
+            (Some(Action::Decline), _) | (_, Some(Action::Cancel)) => {
+                app.confirm_clear_queue = false
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    // The help overlay sits on top of everything else
+    if app.help_scroll.is_some() {
+        match (key_event.code, action) {
+            _ if ctrl_c => app.quit(),
+            (KeyCode::Esc, _) | (_, Some(Action::Help)) => app.toggle_help(),
+            (KeyCode::PageUp, _) => app.scroll_help(true, 10),
+            (KeyCode::PageDown, _) => app.scroll_help(false, 10),
+            (_, Some(Action::Up)) => app.scroll_help(true, 1),
+            (_, Some(Action::Down)) => app.scroll_help(false, 1),
+            (_, Some(Action::Top)) => app.scroll_help(true, usize::MAX),
+            (_, Some(Action::Bottom)) => app.scroll_help(false, usize::MAX),
+            (_, Some(Action::Quit)) => app.quit(),
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    // The log viewer takes over until it is closed
+    if app.log_scroll.is_some() {
+        match (key_event.code, action) {
+            _ if ctrl_c => app.quit(),
+            (KeyCode::Esc, _) | (_, Some(Action::ToggleLog)) => app.toggle_log(),
+            (KeyCode::PageUp, _) => app.scroll_log(true, 10),
+            (KeyCode::PageDown, _) => app.scroll_log(false, 10),
+            (_, Some(Action::Up)) => app.scroll_log(true, 1),
+            (_, Some(Action::Down)) => app.scroll_log(false, 1),
+            (_, Some(Action::Top)) => app.scroll_log(true, usize::MAX),
+            (_, Some(Action::Bottom)) => app.scroll_log(false, usize::MAX),
+            (_, Some(Action::Quit)) => app.quit(),
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    // The sync view takes over until it is closed
+    if let Some(sync) = app.sync.as_mut() {
+        match (key_event.code, action) {
+            _ if ctrl_c => app.quit(),
+            (KeyCode::Esc, _) | (_, Some(Action::ToggleSync)) => app.toggle_sync(),
+            (_, Some(Action::UploadAll)) => sync_uploads(app).await?,
+            (_, Some(Action::Download)) => sync_downloads(app).await,
+            (_, Some(Action::Up)) => sync.state.select_previous(),
+            (_, Some(Action::Down)) => sync.state.select_next(),
+            (_, Some(Action::Top)) => sync.state.select_first(),
+            (_, Some(Action::Bottom)) => sync.state.select_last(),
+            (_, Some(Action::Activate)) => handle_file_actions(app).await?,
+            (_, Some(Action::Quit)) => app.quit(),
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    // The history view takes over until it is closed
+    if let Some(view) = app.history_view.as_mut() {
+        match (key_event.code, action) {
+            _ if ctrl_c => app.quit(),
+            (KeyCode::Up, _) => view.state.select_previous(),
+            (KeyCode::Down, _) => view.state.select_next(),
+            (KeyCode::Enter, _) => handle_file_actions(app).await?,
+            (KeyCode::Esc, _) if view.editing => view.editing = false,
+            (KeyCode::Backspace, _) if view.editing => app.history_query_pop(),
+            (KeyCode::Char(character), _) if view.editing => app.history_query_push(character),
+            (_, Some(Action::Search)) => view.editing = true,
+            // Esc drops the filter before it closes the view
+            (KeyCode::Esc, _) if !view.query.is_empty() => app.clear_history_query(),
+            (KeyCode::Esc, _) | (_, Some(Action::ToggleHistory)) => app.toggle_history(),
+            (_, Some(Action::Requeue)) => requeue_history(app).await,
+            (_, Some(Action::ExportQueue)) => app.export_history(),
+            (_, Some(Action::Up)) => view.state.select_previous(),
+            (_, Some(Action::Down)) => view.state.select_next(),
+            (_, Some(Action::Top)) => view.state.select_first(),
+            (_, Some(Action::Bottom)) => view.state.select_last(),
+            (_, Some(Action::Activate)) => requeue_history(app).await,
+            (_, Some(Action::Quit)) => app.quit(),
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    // The chat box takes every key it can type
+    if app.focus == Panel::Chat {
+        match key_event.code {
+            _ if ctrl_c => app.quit(),
+            KeyCode::Esc => app.focus_panel(Panel::Client),
+            KeyCode::Tab => app.cycle_focus(true),
+            KeyCode::BackTab => app.cycle_focus(false),
+            KeyCode::Enter => handle_file_actions(app).await?,
+            KeyCode::Backspace => {
+                app.chat_input.pop();
+            }
+            KeyCode::Up => app.scroll_chat(true, 1),
+            KeyCode::Down => app.scroll_chat(false, 1),
+            KeyCode::PageUp => app.scroll_chat(true, 10),
+            KeyCode::PageDown => app.scroll_chat(false, 10),
+            // Ctrl and Alt chords aren't text
+            KeyCode::Char(character) if (key_event.modifiers - KeyModifiers::SHIFT).is_empty() => {
+                app.chat_input.push(character)
+            }
+            _ => {}
+        }
+        return Ok(());
+    }
+
+    // Everything else goes through the keymap, sequences wait for their next key
+    let key = Key::from(key_event);
+    app.pending_keys.push(key);
+    let lookup = match app.keymap.lookup(&app.pending_keys) {
+        // A key breaking off a sequence may start another one
+        Lookup::Unbound if app.pending_keys.len() > 1 => {
+            app.pending_keys = vec![key];
+            app.keymap.lookup(&app.pending_keys)
+        }
+        lookup => lookup,
+    };
+    match lookup {
+        Lookup::Pending => Ok(()),
+        Lookup::Action(action) => {
+            app.pending_keys.clear();
+            run_action(action, app).await
+        }
+        Lookup::Unbound => {
+            app.pending_keys.clear();
+            Ok(())
         }
-        KeyCode::Up => {
+    }
+}
+
+/// Does what a key of the keymap is bound to. Panel specific actions
+/// do nothing in the other panels.
+async fn run_action(action: Action, app: &mut App<'_>) -> AppResult<()> {
+    let focus = app.focus;
+    match action {
+        // Clear an active search filter before quitting
+        Action::Cancel if app.search.is_some() => app.cancel_search(),
+        Action::Cancel | Action::Quit => app.quit(),
+        Action::Search => app.start_search(),
+        // Queue management, the selection follows the moved song
+        Action::MoveUp if focus == Panel::Queue => {
+            edit_selected(QueueEdit::MoveUp, app).await;
             app.handle_fs_state("up");
         }
-        KeyCode::Down => {
+        Action::MoveDown if focus == Panel::Queue => {
+            edit_selected(QueueEdit::MoveDown, app).await;
             app.handle_fs_state("down");
         }
-        KeyCode::Tab => {
-            app.switch_fs();
+        // The song on air only leaves through a skip vote
+        Action::Remove if focus == Panel::Queue && app.get_queue_index() == Some(0) => app.notify(
+            Severity::Warning,
+            "The current song can only be voted off".to_string(),
+        ),
+        Action::Remove if focus == Panel::Queue => edit_selected(QueueEdit::Remove, app).await,
+        Action::PlayNext if focus == Panel::Queue => edit_selected(QueueEdit::PlayNext, app).await,
+        Action::ClearQueue if focus == Panel::Queue => app.confirm_clear_queue = true,
+        Action::Upvote if focus == Panel::Queue => {
+            if let Some(index) = app.get_queue_index() {
+                vote(Vote::Up, index, app).await;
+            }
+        }
+        Action::Downvote if focus == Panel::Queue => {
+            if let Some(index) = app.get_queue_index() {
+                vote(Vote::Down, index, app).await;
+            }
+        }
+        // Skipping takes enough listeners agreeing, pressing again takes the vote back
+        Action::VoteSkip => vote(Vote::Skip, 0, app).await,
+        // Moving a song outside the queue is plain moving
+        Action::Up | Action::MoveUp => app.handle_fs_state("up"),
+        Action::Down | Action::MoveDown => app.handle_fs_state("down"),
+        Action::Top => app.select_edge(true),
+        Action::Bottom => app.select_edge(false),
+        Action::NextPanel => app.cycle_focus(true),
+        Action::PreviousPanel => app.cycle_focus(false),
+        Action::Activate => handle_file_actions(app).await?,
+        Action::ToggleTranscode => app.toggle_transcode(),
+        Action::FocusChat => app.focus_panel(Panel::Chat),
+        Action::ToggleSync => app.toggle_sync(),
+        Action::ExportQueue => app.export_queue(),
+        Action::ToggleHistory => app.toggle_history(),
+        Action::ToggleLog => app.toggle_log(),
+        Action::Zoom => app.toggle_zoom(),
+        Action::Help => app.toggle_help(),
+        Action::Download if focus == Panel::Server => {
+            let marked = app.take_server_marks();
+            if marked.is_empty() {
+                if let Some(song_name) = app.get_song() {
+                    download_song(song_name, app).await;
+                }
+            }
+            for song_name in marked {
+                if !download_song(song_name, app).await {
+                    break;
+                }
+            }
+        }
+        // Favorites and ratings of library songs
+        Action::Favorite if focus == Panel::Server => app.toggle_favorite(),
+        Action::Rate if focus == Panel::Server => app.cycle_stars(),
+        Action::FavoritesOnly if focus == Panel::Server => app.toggle_favorites_only(),
+        Action::RandomFavorite => match app.random_favorite() {
+            Some(song_name) => {
+                queue_song(song_name, app).await;
+            }
+            None => app.notify(
+                Severity::Info,
+                "No favorites on this server yet".to_string(),
+            ),
+        },
+        // Marks for batch actions
+        Action::Mark => app.toggle_mark(),
+        Action::MarkAll => app.mark_all(),
+        Action::ClearMarks => app.clear_marks(),
+        // Reactions to the current song
+        Action::React(kind) => {
+            if let Err(err) = sendReaction(kind, app).await {
+                app.notify(Severity::Error, format!("Can't send the reaction: {}", err));
+            }
+        }
+        // Local file browser
+        Action::LeaveDir if focus == Panel::Client => app.leave_dir(),
+        Action::ToggleHidden if focus == Panel::Client => app.toggle_hidden(),
+        Action::ToggleAudioOnly if focus == Panel::Client => app.toggle_audio_only(),
+        Action::CycleSort if focus == Panel::Client => app.cycle_sort(),
+        Action::GoHome if focus == Panel::Client => app.go_home(),
+        Action::GoSongDir if focus == Panel::Client => app.go_song_dir(),
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Handles clicks and the scroll wheel, hit-tested against the areas of the last render.
+pub async fn handle_mouse_events(mouse_event: MouseEvent, app: &mut App<'_>) -> AppResult<()> {
+    let position = Position::new(mouse_event.column, mouse_event.row);
+    let up = match mouse_event.kind {
+        MouseEventKind::ScrollUp => true,
+        MouseEventKind::ScrollDown => false,
+        MouseEventKind::Down(MouseButton::Left) => return handle_click(position, app).await,
+        _ => return Ok(()),
+    };
+
+    // Overlays scroll first, then whatever list is under the pointer
+    if app.help_scroll.is_some() {
+        app.scroll_help(up, 3);
+        return Ok(());
+    }
+    if app.log_scroll.is_some() {
+        app.scroll_log(up, 3);
+        return Ok(());
+    }
+    let view_state = match (app.sync.as_mut(), app.history_view.as_mut()) {
+        (Some(sync), _) => Some(&mut sync.state),
+        (_, Some(view)) => Some(&mut view.state),
+        _ => None,
+    };
+    if let Some(state) = view_state {
+        if up {
+            state.scroll_up_by(3);
+        } else {
+            state.scroll_down_by(3);
+        }
+        return Ok(());
+    }
+    match app.panel_at(position) {
+        Some(Panel::Chat) => app.scroll_chat(up, 3),
+        Some(panel) => {
+            if app.focus != panel {
+                app.focus_panel(panel);
+            }
+            for _ in 0..3 {
+                app.handle_fs_state(if up { "up" } else { "down" });
+            }
+        }
+        None => {}
+    }
+    Ok(())
+}
+
+/// Focuses and selects what was clicked, a double click acts on it like Enter.
+async fn handle_click(position: Position, app: &mut App<'_>) -> AppResult<()> {
+    if app.help_scroll.is_some() {
+        app.toggle_help();
+        return Ok(());
+    }
+    if app.playlist_import.is_some() || app.confirm_clear_queue {
+        return Ok(());
+    }
+    let double = app.double_click(position);
+
+    // The sync and history views cover both explorers
+    let views = app.areas.views;
+    let view_state = match (app.sync.as_mut(), app.history_view.as_mut()) {
+        (Some(sync), _) => Some((&mut sync.state, sync.entries.len())),
+        (_, Some(view)) => Some((&mut view.state, view.matches.len())),
+        _ => None,
+    };
+    if let Some((state, rows)) = view_state {
+        if !views.contains(position) || position.y == views.y {
+            return Ok(());
         }
-        KeyCode::Enter => {
-            app.handle_fs_actions();
+        let row = (position.y - views.y - 1) as usize + state.offset();
+        if row < rows {
+            state.select(Some(row));
+            if double {
+                handle_file_actions(app).await?;
+            }
         }
+        return Ok(());
+    }
+
+    if app.areas.gauge.contains(position) {
+        app.toggle_position();
+        return Ok(());
+    }
+    if let Some(panel) = app.tab_at(position) {
+        app.focus_panel(panel);
+        return Ok(());
+    }
+    match app.panel_at(position) {
+        Some(Panel::Chat) if app.focus != Panel::Chat => app.focus_panel(Panel::Chat),
+        Some(panel) if app.select_at(panel, position) && double => handle_file_actions(app).await?,
         _ => {}
     }
     Ok(())
 }
 
-pub fn handle_network_communication(message: &[u8; 1024], app: &mut App) -> AppResult<()> {
-    // Convert array of u8 to json string
-    let trimmed_message = message.split(|&byte| byte == 0).next().unwrap_or(message);
-    let update_string = match std::str::from_utf8(trimmed_message) {
-        Ok(update_string) => update_string.trim(),
+pub fn handle_network_communication(message: &str, app: &mut App) -> AppResult<()> {
+    // convert json String to one of the server message data structures
+    let server_message: ServerMessage = match serde_json::from_str(message) {
+        Ok(server_message) => server_message,
         Err(err) => {
-            eprintln!("Failed to parse message: {}", err);
-            "ki chuj"
+            app.notify(
+                Severity::Warning,
+                format!("Can't read a server message: {}", err),
+            );
+            return Ok(());
         }
     };
 
-    // convert json String to ServerState data strucure
-    let server_state: ServerState = serde_json::from_str(update_string).unwrap();
+    match server_message {
+        ServerMessage::Handshake { handshake } => app.update_codec(handshake),
+        ServerMessage::Chat { chat } => app.push_chat(chat),
+        ServerMessage::Reaction { reaction } => app.push_reaction(reaction),
+        ServerMessage::Download { download } => app.start_download(download),
+        ServerMessage::DownloadChunk { download_chunk } => app.download_chunk(download_chunk),
+        ServerMessage::State(server_state) => app.update_state(server_state),
+    }
+
+    Ok(())
+}
 
-    app.update_state(server_state);
+/// Queues a library song, a failed send is reported instead of ending the
+/// session. False when the request didn't go out.
+async fn queue_song(song_name: String, app: &mut App<'_>) -> bool {
+    match toQueue(song_name.clone(), app).await {
+        Ok(()) => true,
+        Err(err) => {
+            app.notify(
+                Severity::Error,
+                format!("Can't queue {}: {}", song_name, err),
+            );
+            false
+        }
+    }
+}
+
+/// Asks for a library song, a failed send is reported instead of ending the
+/// session. False when the request didn't go out.
+async fn download_song(song_name: String, app: &mut App<'_>) -> bool {
+    match requestDownload(song_name.clone(), app).await {
+        Ok(()) => true,
+        Err(err) => {
+            app.notify(
+                Severity::Error,
+                format!("Can't download {}: {}", song_name, err),
+            );
+            false
+        }
+    }
+}
 
+/// Uploads a local song, a file that vanished or can't be decoded is reported
+/// instead of ending the session. False when the song didn't go up.
+async fn upload_file(file_path: String, app: &mut App<'_>) -> bool {
+    match send_file(file_path.clone(), app).await {
+        Ok(uploaded) => uploaded,
+        Err(err) => {
+            app.notify(
+                Severity::Error,
+                format!("Can't upload {}: {}", file_path, err),
+            );
+            false
+        }
+    }
+}
+
+/// Uploads a local song with its tags, converting it first when needed.
+async fn send_file(file_path: String, app: &mut App<'_>) -> AppResult<bool> {
+    let probe = app.probe_client_song(&file_path).clone();
+    match probe.support {
+        Support::Unsupported => {
+            app.notify(
+                Severity::Error,
+                format!("Refusing to upload {}: {}", file_path, probe.describe()),
+            );
+            return Ok(false);
+        }
+        Support::Transcode if app.transcode_uploads => {
+            let wav_path = Path::new(&server_song_path(&file_path))
+                .with_extension("wav")
+                .to_string_lossy()
+                .to_string();
+            let wav = tokio::task::spawn_blocking(move || transcode(&file_path)).await??;
+            sendSongData(wav_path.clone(), wav, app).await?;
+            sendMetadata(wav_path, &probe, app).await?;
+        }
+        Support::Transcode => {
+            app.notify(
+                Severity::Warning,
+                format!("{} is not in the server format, uploading as-is", file_path),
+            );
+            sendSong(file_path.clone(), app).await?;
+            sendMetadata(server_song_path(&file_path), &probe, app).await?;
+        }
+        Support::Streamable => {
+            sendSong(file_path.clone(), app).await?;
+            sendMetadata(server_song_path(&file_path), &probe, app).await?;
+        }
+    }
+    Ok(true)
+}
+
+/// Uploads every song that is only in the local song directory.
+async fn sync_uploads(app: &mut App<'_>) -> AppResult<()> {
+    let paths = app
+        .sync_entries(SyncStatus::LocalOnly)
+        .into_iter()
+        .filter_map(|entry| entry.path)
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    app.start_upload_batch(paths);
     Ok(())
 }
 
-pub async fn handle_file_actions(app: &mut App<'_>) -> AppResult<()> {
-    if app.client_fs_selected {
-        let file_path = app.get_client_song_path();
-        sendSong(file_path, app).await?;
+/// Enqueues a playlist, asking first when songs have to be uploaded.
+async fn import_playlist(path: &Path, app: &mut App<'_>) {
+    let import = match app.resolve_playlist(path) {
+        Ok(import) => import,
+        Err(err) => {
+            app.notify(
+                Severity::Error,
+                format!("Can't read {}: {}", path.display(), err),
+            );
+            return;
+        }
+    };
+    if import.unresolved > 0 {
+        app.notify(
+            Severity::Warning,
+            format!(
+                "{}: {} songs found neither on the server nor locally",
+                import.name, import.unresolved
+            ),
+        );
+    }
+    if import.missing.is_empty() {
+        for song_name in import.songs {
+            if !queue_song(song_name, app).await {
+                break;
+            }
+        }
     } else {
-        let song_name = app.get_song();
-        toQueue(song_name, app).await?;
+        app.playlist_import = Some(import);
+    }
+}
+
+/// Answer to the playlist prompt: upload the missing songs and enqueue
+/// everything once they are up, or enqueue only what the server has.
+async fn finish_import(upload: bool, app: &mut App<'_>) {
+    let Some(import) = app.playlist_import.take() else {
+        return;
+    };
+    if upload {
+        let paths = import
+            .missing
+            .iter()
+            .map(|(path, _)| path.to_string_lossy().to_string())
+            .collect();
+        app.start_upload_batch(paths);
+        app.enqueue_after_batch.extend(import.songs);
+        return;
+    }
+    for song_name in import.songs {
+        if !import.missing.iter().any(|(_, name)| *name == song_name)
+            && !queue_song(song_name, app).await
+        {
+            break;
+        }
+    }
+}
+
+/// Puts the highlighted song of the history view back in the queue.
+async fn requeue_history(app: &mut App<'_>) {
+    let Some(entry) = app.history_entry() else {
+        return;
+    };
+    if !app.state.song_library.contains(&entry.song) {
+        app.notify(
+            Severity::Warning,
+            format!("{} is no longer on the server", entry.song),
+        );
+        return;
+    }
+    queue_song(entry.song, app).await;
+}
+
+/// Uploads the next song of a running batch.
+pub async fn handle_batch(app: &mut App<'_>) {
+    let Some(batch) = app.upload_batch.as_mut() else {
+        return;
+    };
+    let Some(path) = batch.pending.pop_front() else {
+        let total = batch.total;
+        let failed = std::mem::take(&mut batch.failed);
+        app.upload_batch = None;
+        if failed.is_empty() {
+            app.notify(Severity::Info, format!("Uploaded {} songs", total));
+        } else {
+            app.notify(
+                Severity::Warning,
+                format!(
+                    "Uploaded {} of {} songs, failed: {}",
+                    total - failed.len(),
+                    total,
+                    failed.join(", ")
+                ),
+            );
+        }
+        // Uploads went out first on the same socket, the server has them by now
+        for song_name in std::mem::take(&mut app.enqueue_after_batch) {
+            if !queue_song(song_name, app).await {
+                break;
+            }
+        }
+        return;
+    };
+    // One bad file doesn't stop the rest of the batch
+    if !upload_file(path.clone(), app).await {
+        let name = Path::new(&path)
+            .file_name()
+            .map_or(path.clone(), |name| name.to_string_lossy().to_string());
+        if let Some(batch) = app.upload_batch.as_mut() {
+            batch.failed.push(name);
+        }
+    }
+}
+
+/// Downloads every song that is only in the server library.
+async fn sync_downloads(app: &mut App<'_>) {
+    let songs = app.sync_entries(SyncStatus::ServerOnly);
+    app.notify(Severity::Info, format!("Downloading {} songs", songs.len()));
+    for entry in songs {
+        if !download_song(entry.name, app).await {
+            break;
+        }
+    }
+}
+
+async fn handle_file_actions(app: &mut App<'_>) -> AppResult<()> {
+    // Enter confirms the query and acts on the filtered selection
+    app.stop_search_editing();
+    if let Some(view) = app.history_view.as_mut() {
+        // The first Enter confirms a query being typed
+        if view.editing {
+            view.editing = false;
+        } else {
+            requeue_history(app).await;
+        }
+    } else if app.sync.is_some() {
+        // Local only songs go up, server only and differing ones come down
+        match app.sync_entry() {
+            Some(entry) if entry.status == SyncStatus::LocalOnly => {
+                if let Some(path) = entry.path {
+                    upload_file(path.to_string_lossy().to_string(), app).await;
+                }
+            }
+            Some(entry) if matches!(entry.status, SyncStatus::ServerOnly | SyncStatus::Differs) => {
+                download_song(entry.name, app).await;
+            }
+            _ => {}
+        }
+    } else if app.focus == Panel::Chat {
+        let text = std::mem::take(&mut app.chat_input);
+        if text.trim().is_empty() {
+            return Ok(());
+        }
+        match sendChat(text.clone(), app).await {
+            Ok(()) => app.chat_scroll = 0,
+            Err(err) => {
+                app.notify(Severity::Error, format!("Can't send the message: {}", err));
+                // Kept for another try
+                app.chat_input = text;
+            }
+        }
+    } else if app.focus == Panel::Queue {
+        edit_selected(QueueEdit::PlayNext, app).await;
+    } else if app.focus == Panel::Client && !app.client_marks.is_empty() {
+        let mut paths: Vec<String> = std::mem::take(&mut app.client_marks)
+            .into_iter()
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        paths.sort();
+        app.start_upload_batch(paths);
+    } else if app.focus == Panel::Client {
+        let Some(entry) = app.get_client_entry() else {
+            return Ok(());
+        };
+        if entry.is_dir {
+            app.enter_dir(&entry.path);
+            return Ok(());
+        }
+        if entry.is_playlist() {
+            import_playlist(&entry.path, app).await;
+            return Ok(());
+        }
+        upload_file(entry.path_string(), app).await;
+    } else if !app.server_marks.is_empty() {
+        for song_name in app.take_server_marks() {
+            if !queue_song(song_name, app).await {
+                break;
+            }
+        }
+    } else if let Some(song_name) = app.get_song() {
+        queue_song(song_name, app).await;
     }
 
     Ok(())
@@ -0,0 +1,324 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{CodecParameters, CODEC_TYPE_MP3, CODEC_TYPE_NULL, CODEC_TYPE_PCM_S16LE};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey};
+use symphonia::core::probe::{Hint, ProbeResult};
+
+// Extensions the client can probe and decode
+const AUDIO_EXTENSIONS: [&str; 4] = ["mp3", "wav", "flac", "ogg"];
+
+// Server canonical format: WAV, PCM s16le, 48 kHz, stereo
+// (the layout the opus transport encodes from)
+pub const CANONICAL_SAMPLE_RATE: u32 = 48000;
+pub const CANONICAL_CHANNELS: usize = 2;
+
+/// What the stream path can do with a local file.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Support {
+    /// Canonical WAV or MP3, whose frames survive being sliced into chunks
+    Streamable,
+    /// Decodable, but has to be converted to the canonical format to stream
+    Transcode,
+    /// Not audio, or a codec the client can't decode
+    Unsupported,
+}
+
+/// Result of probing a local audio file.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProbeInfo {
+    pub format: String,
+    pub sample_rate: Option<u32>,
+    pub channels: Option<usize>,
+    pub duration: Option<Duration>,
+    pub support: Support,
+    /// ID3 / Vorbis comment / FLAC tags
+    pub artist: Option<String>,
+    pub title: Option<String>,
+    pub album: Option<String>,
+    /// Modification time the probe was taken at
+    pub modified: Option<SystemTime>,
+    /// Stand-in while the file is probed in the background
+    #[serde(skip)]
+    pub pending: bool,
+}
+
+/// Shown for a listed file until its first probe is done.
+pub static PENDING: ProbeInfo = ProbeInfo {
+    format: String::new(),
+    sample_rate: None,
+    channels: None,
+    duration: None,
+    support: Support::Unsupported,
+    artist: None,
+    title: None,
+    album: None,
+    modified: None,
+    pending: true,
+};
+
+/// Shown for files that aren't audio, they are never opened.
+pub static NOT_AUDIO: ProbeInfo = ProbeInfo::unsupported(None);
+
+impl ProbeInfo {
+    const fn unsupported(modified: Option<SystemTime>) -> Self {
+        Self {
+            format: String::new(),
+            sample_rate: None,
+            channels: None,
+            duration: None,
+            support: Support::Unsupported,
+            artist: None,
+            title: None,
+            album: None,
+            modified,
+            pending: false,
+        }
+    }
+
+    fn read_tags(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let value = Some(tag.value.to_string().replace('\n', " "));
+            match tag.std_key {
+                Some(StandardTagKey::Artist) => self.artist = value,
+                Some(StandardTagKey::TrackTitle) => self.title = value,
+                Some(StandardTagKey::Album) => self.album = value,
+                _ => {}
+            }
+        }
+    }
+
+    /// Short summary shown next to the file name.
+    pub fn describe(&self) -> String {
+        if self.pending {
+            return "…".to_string();
+        }
+        if self.support == Support::Unsupported {
+            return "unsupported".to_string();
+        }
+        let mut parts = vec![self.format.clone()];
+        if let Some(sample_rate) = self.sample_rate {
+            parts.push(format!("{:.1}kHz", sample_rate as f32 / 1000.0));
+        }
+        if let Some(channels) = self.channels {
+            parts.push(format!("{}ch", channels));
+        }
+        if self.support == Support::Transcode {
+            parts.push("needs transcode".to_string());
+        }
+        parts.join(" ")
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Whether a file has the extension of a format the client decodes.
+pub fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+pub fn modified(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
+
+fn open(path: &str) -> Result<ProbeResult, Error> {
+    let file = File::open(path)?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    symphonia::default::get_probe().format(
+        &hint,
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )
+}
+
+fn audio_track(format: &dyn FormatReader) -> Option<(u32, CodecParameters)> {
+    format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .map(|track| (track.id, track.codec_params.clone()))
+}
+
+/// Reads format, sample rate, channels, duration and tags of a local file.
+pub fn probe(path: &str) -> ProbeInfo {
+    let modified = modified(path);
+    let Ok(mut probed) = open(path) else {
+        return ProbeInfo::unsupported(modified);
+    };
+    let Some((_, params)) = audio_track(probed.format.as_ref()) else {
+        return ProbeInfo::unsupported(modified);
+    };
+
+    let codecs = symphonia::default::get_codecs();
+    let Some(descriptor) = codecs.get_codec(params.codec) else {
+        return ProbeInfo::unsupported(modified);
+    };
+
+    let channels = params.channels.map(|channels| channels.count());
+    let duration = match (params.time_base, params.n_frames) {
+        (Some(time_base), Some(frames)) => {
+            let time = time_base.calc_time(frames);
+            Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+        }
+        _ => None,
+    };
+
+    let canonical = params.codec == CODEC_TYPE_PCM_S16LE
+        && params.sample_rate == Some(CANONICAL_SAMPLE_RATE)
+        && channels == Some(CANONICAL_CHANNELS);
+    let support = if canonical || params.codec == CODEC_TYPE_MP3 {
+        Support::Streamable
+    } else {
+        Support::Transcode
+    };
+
+    let mut info = ProbeInfo {
+        format: descriptor.short_name.to_string(),
+        sample_rate: params.sample_rate,
+        channels,
+        duration,
+        support,
+        artist: None,
+        title: None,
+        album: None,
+        modified,
+        pending: false,
+    };
+
+    // ID3v2 sits before the container, Vorbis comments / FLAC tags inside it
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|meta| meta.current()) {
+        info.read_tags(revision);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        info.read_tags(revision);
+    }
+
+    info
+}
+
+/// Probe results persisted between runs, keyed by path
+pub fn load_index(path: &str) -> HashMap<String, ProbeInfo> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|index| serde_json::from_str(&index).ok())
+        .unwrap_or_default()
+}
+
+pub fn save_index(path: &str, index: &HashMap<String, ProbeInfo>) -> std::io::Result<()> {
+    if let Some(dir) = Path::new(path).parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(path, serde_json::to_string(index)?)
+}
+
+/// Decodes a local file and re-encodes it as canonical WAV.
+pub fn transcode(path: &str) -> Result<Vec<u8>, Error> {
+    let mut format = open(path)?.format;
+    let (track_id, params) =
+        audio_track(format.as_ref()).ok_or(Error::Unsupported("no audio track"))?;
+    let mut decoder = symphonia::default::get_codecs().make(&params, &Default::default())?;
+
+    let mut sample_rate = params.sample_rate.unwrap_or(CANONICAL_SAMPLE_RATE);
+    // Interleaved stereo at the source sample rate
+    let mut stereo: Vec<f32> = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(Error::ResetRequired) => break,
+            Err(err) => return Err(err),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            // Skip corrupted frames instead of failing the whole upload
+            Err(Error::DecodeError(_)) => continue,
+            Err(err) => return Err(err),
+        };
+        let spec = *decoded.spec();
+        sample_rate = spec.rate;
+        let channels = spec.channels.count();
+        let mut samples = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+        samples.copy_interleaved_ref(decoded);
+        for frame in samples.samples().chunks(channels) {
+            let left = frame[0];
+            let right = if channels > 1 { frame[1] } else { frame[0] };
+            stereo.push(left);
+            stereo.push(right);
+        }
+    }
+
+    let pcm = resample(&stereo, sample_rate, CANONICAL_SAMPLE_RATE);
+    Ok(wav(&pcm))
+}
+
+// Linear interpolation is plenty for a radio stream
+fn resample(stereo: &[f32], from: u32, to: u32) -> Vec<f32> {
+    if from == to || stereo.is_empty() {
+        return stereo.to_vec();
+    }
+    let frames = stereo.len() / 2;
+    let ratio = from as f64 / to as f64;
+    let out_frames = (frames as f64 / ratio) as usize;
+    let mut out = Vec::with_capacity(out_frames * 2);
+    for i in 0..out_frames {
+        let position = i as f64 * ratio;
+        let index = position as usize;
+        let next = (index + 1).min(frames - 1);
+        let fraction = (position - index as f64) as f32;
+        for channel in 0..2 {
+            let a = stereo[index * 2 + channel];
+            let b = stereo[next * 2 + channel];
+            out.push(a + (b - a) * fraction);
+        }
+    }
+    out
+}
+
+// 44 byte RIFF header followed by s16le samples
+fn wav(stereo: &[f32]) -> Vec<u8> {
+    let channels = CANONICAL_CHANNELS as u16;
+    let block_align = channels * 2;
+    let byte_rate = CANONICAL_SAMPLE_RATE * block_align as u32;
+    let data_size = (stereo.len() * 2) as u32;
+
+    let mut out: Vec<u8> = Vec::with_capacity(44 + data_size as usize);
+    out.extend(b"RIFF");
+    out.extend(&(36 + data_size).to_le_bytes());
+    out.extend(b"WAVE");
+    out.extend(b"fmt ");
+    out.extend(&16u32.to_le_bytes());
+    out.extend(&1u16.to_le_bytes()); // PCM
+    out.extend(&channels.to_le_bytes());
+    out.extend(&CANONICAL_SAMPLE_RATE.to_le_bytes());
+    out.extend(&byte_rate.to_le_bytes());
+    out.extend(&block_align.to_le_bytes());
+    out.extend(&16u16.to_le_bytes());
+    out.extend(b"data");
+    out.extend(&data_size.to_le_bytes());
+    for sample in stereo {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        out.extend(&sample.to_le_bytes());
+    }
+    out
+}
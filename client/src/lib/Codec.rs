@@ -0,0 +1,200 @@
+use serde::Deserialize;
+
+// Audio transport negotiated with the server during the handshake. The audio
+// stream starts with a marker, "JAM" and the codec's initial, then
+// Raw    -> slices of the original file, decoded chunk by chunk by rodio
+// Opus   -> packetized opus stream [packet_size 2B | packet var], songs the
+//           server can't encode come as raw slices with the size's top bit set
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    Raw,
+    Opus,
+}
+
+impl Codec {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Codec::Raw => "raw",
+            Codec::Opus => "opus",
+        }
+    }
+
+    /// Codec announced by the marker that starts the audio stream.
+    fn from_marker(marker: &[u8]) -> Option<Self> {
+        match marker {
+            b"JAMr" => Some(Codec::Raw),
+            b"JAMo" => Some(Codec::Opus),
+            _ => None,
+        }
+    }
+}
+
+const MARKER_SIZE: usize = 4;
+
+/// Codecs this build can decode, in order of preference.
+pub fn supported_codecs() -> Vec<Codec> {
+    if cfg!(feature = "opus") {
+        vec![Codec::Opus, Codec::Raw]
+    } else {
+        vec![Codec::Raw]
+    }
+}
+
+/// Unit of audio handed from the stream reader to playback.
+#[derive(Debug)]
+pub enum AudioPacket {
+    Raw(Vec<u8>),
+    Opus(Vec<u8>),
+}
+
+// Marks a frame of the opus stream that carries a raw slice
+const RAW_FRAME: u16 = 0x8000;
+
+/// Splits the audio stream back into packets, in the codec its marker names.
+#[derive(Debug, Default)]
+pub struct PacketReader {
+    /// Unknown until the marker has arrived
+    codec: Option<Codec>,
+    buffer: Vec<u8>,
+}
+
+impl PacketReader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Packets completed by the data read from the stream. Errors when the
+    /// stream doesn't start with a marker.
+    pub fn push(&mut self, data: &[u8]) -> Result<Vec<AudioPacket>, String> {
+        self.buffer.extend_from_slice(data);
+        if self.codec.is_none() {
+            if self.buffer.len() < MARKER_SIZE {
+                return Ok(vec![]);
+            }
+            let marker: Vec<u8> = self.buffer.drain(..MARKER_SIZE).collect();
+            let codec = Codec::from_marker(&marker)
+                .ok_or_else(|| "the server didn't announce a codec".to_string())?;
+            self.codec = Some(codec);
+        }
+        Ok(match self.codec {
+            Some(Codec::Opus) => std::iter::from_fn(|| self.next_packet()).collect(),
+            // Raw slices play as they come
+            _ if self.buffer.is_empty() => vec![],
+            _ => vec![AudioPacket::Raw(std::mem::take(&mut self.buffer))],
+        })
+    }
+
+    /// Returns the next complete packet, if one has been fully received.
+    fn next_packet(&mut self) -> Option<AudioPacket> {
+        if self.buffer.len() < 2 {
+            return None;
+        }
+        let header = u16::from_be_bytes([self.buffer[0], self.buffer[1]]);
+        let packet_size = (header & !RAW_FRAME) as usize;
+        if self.buffer.len() < 2 + packet_size {
+            return None;
+        }
+        let packet = self.buffer[2..2 + packet_size].to_vec();
+        self.buffer.drain(..2 + packet_size);
+        Some(if header & RAW_FRAME != 0 {
+            AudioPacket::Raw(packet)
+        } else {
+            AudioPacket::Opus(packet)
+        })
+    }
+}
+
+pub const OPUS_SAMPLE_RATE: u32 = 48000;
+pub const OPUS_CHANNELS: u16 = 2;
+// Largest frame opus can produce: 120 ms at 48 kHz
+#[cfg(feature = "opus")]
+const OPUS_MAX_FRAME: usize = 5760;
+
+#[cfg(feature = "opus")]
+pub struct OpusDecoder {
+    decoder: audiopus::coder::Decoder,
+    samples: Vec<i16>,
+}
+
+#[cfg(feature = "opus")]
+impl OpusDecoder {
+    pub fn new() -> Option<Self> {
+        let decoder = audiopus::coder::Decoder::new(
+            audiopus::SampleRate::Hz48000,
+            audiopus::Channels::Stereo,
+        )
+        .ok()?;
+        Some(Self {
+            decoder,
+            samples: vec![0; OPUS_MAX_FRAME * OPUS_CHANNELS as usize],
+        })
+    }
+
+    /// Decodes one packet into interleaved 16 bit samples.
+    pub fn decode(&mut self, packet: &[u8]) -> Option<Vec<i16>> {
+        let packet = audiopus::packet::Packet::try_from(packet).ok()?;
+        let output = audiopus::MutSignals::try_from(&mut self.samples).ok()?;
+        let frame_size = self.decoder.decode(Some(packet), output, false).ok()?;
+        Some(self.samples[..frame_size * OPUS_CHANNELS as usize].to_vec())
+    }
+}
+
+// Without libopus the client never advertises opus, so the server never sends it
+#[cfg(not(feature = "opus"))]
+pub struct OpusDecoder;
+
+#[cfg(not(feature = "opus"))]
+impl OpusDecoder {
+    pub fn new() -> Option<Self> {
+        Some(Self)
+    }
+
+    pub fn decode(&mut self, _packet: &[u8]) -> Option<Vec<i16>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(packets: &[AudioPacket]) -> Vec<(bool, usize)> {
+        packets
+            .iter()
+            .map(|packet| match packet {
+                AudioPacket::Raw(data) => (false, data.len()),
+                AudioPacket::Opus(data) => (true, data.len()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn raw_stream_passes_through_after_the_marker() {
+        let mut reader = PacketReader::new();
+        assert_eq!(sizes(&reader.push(b"JA").unwrap()), vec![]);
+        assert_eq!(sizes(&reader.push(b"Mrabc").unwrap()), vec![(false, 3)]);
+        // Bytes that look like a frame header are audio too
+        assert_eq!(sizes(&reader.push(&[0, 9]).unwrap()), vec![(false, 2)]);
+    }
+
+    #[test]
+    fn opus_stream_is_split_into_frames() {
+        let mut reader = PacketReader::new();
+        let mut stream = b"JAMo".to_vec();
+        stream.extend([0, 3, 1, 2, 3]);
+        stream.extend([0x80, 2, 7, 7]);
+        stream.extend([0, 5, 1]);
+        assert_eq!(
+            sizes(&reader.push(&stream).unwrap()),
+            vec![(true, 3), (false, 2)]
+        );
+        assert_eq!(sizes(&reader.push(&[2, 3, 4, 5]).unwrap()), vec![(true, 5)]);
+    }
+
+    #[test]
+    fn rejects_a_stream_without_marker() {
+        assert!(PacketReader::new().push(b"RIFF....").is_err());
+    }
+}
@@ -0,0 +1,153 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+// FNV-1a 64, the hash the server announces downloads with
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+fn fnv1a(mut hash: u64, data: &[u8]) -> u64 {
+    for byte in data {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+/// Hash of a local file, comparable with the ones the server sends.
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    let mut hash = FNV_OFFSET;
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hex(hash));
+        }
+        hash = fnv1a(hash, &buffer[..read]);
+    }
+}
+
+/// How a finished download ended up in the song directory.
+#[derive(Debug)]
+pub enum Outcome {
+    Saved(PathBuf),
+    /// A file with the same name and content was already there
+    AlreadyHave(PathBuf),
+    /// Content didn't match the announced hash, nothing was kept
+    Corrupted,
+}
+
+/// Song being received from the server library.
+#[derive(Debug)]
+pub struct Download {
+    pub name: String,
+    pub size: u64,
+    pub received: u64,
+    expected_hash: String,
+    hash: u64,
+    dir: PathBuf,
+    /// Hidden partial file, so the explorer doesn't list half a song
+    part: PathBuf,
+    file: File,
+}
+
+impl Download {
+    pub fn start(dir: &Path, name: &str, size: u64, expected_hash: &str) -> io::Result<Self> {
+        let part = dir.join(format!(".{}.part", name));
+        Ok(Self {
+            name: name.to_string(),
+            size,
+            received: 0,
+            expected_hash: expected_hash.to_string(),
+            hash: FNV_OFFSET,
+            dir: dir.to_path_buf(),
+            file: File::create(&part)?,
+            part,
+        })
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.write_all(data)?;
+        self.hash = fnv1a(self.hash, data);
+        self.received += data.len() as u64;
+        Ok(())
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received >= self.size
+    }
+
+    /// Received share in percent.
+    pub fn progress(&self) -> u64 {
+        (self.received * 100).checked_div(self.size).unwrap_or(100)
+    }
+
+    /// Verifies the content and moves it into place, next to an existing
+    /// different song of the same name if there is one.
+    pub fn finish(self) -> io::Result<Outcome> {
+        drop(self.file);
+        if hex(self.hash) != self.expected_hash {
+            fs::remove_file(&self.part)?;
+            return Ok(Outcome::Corrupted);
+        }
+
+        let target = self.dir.join(&self.name);
+        if target.exists() {
+            if hash_file(&target)? == self.expected_hash {
+                fs::remove_file(&self.part)?;
+                return Ok(Outcome::AlreadyHave(target));
+            }
+            let target = free_name(&target);
+            fs::rename(&self.part, &target)?;
+            return Ok(Outcome::Saved(target));
+        }
+        fs::rename(&self.part, &target)?;
+        Ok(Outcome::Saved(target))
+    }
+
+    /// Drops the partial file of a download that won't complete.
+    pub fn abort(self) {
+        drop(self.file);
+        let _ = fs::remove_file(&self.part);
+    }
+}
+
+// "song.mp3" -> "song (1).mp3", "song (2).mp3", ...
+fn free_name(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, extension)))
+        .find(|candidate| !candidate.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_fnv1a_64() {
+        assert_eq!(hex(fnv1a(FNV_OFFSET, b"")), "cbf29ce484222325");
+        assert_eq!(hex(fnv1a(FNV_OFFSET, b"a")), "af63dc4c8601ec8c");
+        assert_eq!(hex(fnv1a(FNV_OFFSET, b"foobar")), "85944171f73967e8");
+    }
+
+    #[test]
+    fn hashes_in_pieces_like_at_once() {
+        let data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let pieces = data.chunks(777).fold(FNV_OFFSET, fnv1a);
+        assert_eq!(pieces, fnv1a(FNV_OFFSET, &data));
+    }
+}
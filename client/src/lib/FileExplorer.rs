@@ -1,14 +1,180 @@
+use crate::lib::{AudioFile, Playlist};
+use std::cmp::Ordering;
 use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-pub fn get_dir_contents(src_dir: &str) -> Vec<String> {
-    let path = fs::read_dir(src_dir).unwrap();
-    let mut files: Vec<String> = vec![];
+/// One row of the local file browser.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+impl Entry {
+    pub fn is_hidden(&self) -> bool {
+        self.name.starts_with('.')
+    }
+
+    pub fn is_audio(&self) -> bool {
+        AudioFile::is_audio(&self.path)
+    }
 
-    for file in path {
-        let path_name = file.unwrap().file_name();
-        let file: String = path_name.into_string().unwrap();
-        files.push(file);
+    pub fn is_playlist(&self) -> bool {
+        Playlist::is_playlist(&self.path)
+    }
+
+    pub fn path_string(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Date,
+    Size,
+    Duration,
+}
+
+impl SortKey {
+    pub fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Date,
+            SortKey::Date => SortKey::Size,
+            SortKey::Size => SortKey::Duration,
+            SortKey::Duration => SortKey::Name,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SortKey::Name => "name",
+            SortKey::Date => "date",
+            SortKey::Size => "size",
+            SortKey::Duration => "duration",
+        }
+    }
+}
+
+pub fn get_dir_contents(src_dir: &Path) -> Vec<Entry> {
+    let Ok(path) = fs::read_dir(src_dir) else {
+        return vec![];
+    };
+    let mut files: Vec<Entry> = vec![];
+
+    for file in path.flatten() {
+        let Ok(name) = file.file_name().into_string() else {
+            continue;
+        };
+        // Follows symlinks so linked music folders can be entered
+        let meta = fs::metadata(file.path()).ok();
+        files.push(Entry {
+            name,
+            path: file.path(),
+            is_dir: meta.as_ref().is_some_and(|meta| meta.is_dir()),
+            size: meta.as_ref().map_or(0, |meta| meta.len()),
+            modified: meta.and_then(|meta| meta.modified().ok()),
+        });
     }
 
     files
 }
+
+/// Local file browser state: where we are and how the listing is shaped.
+#[derive(Debug, Clone)]
+pub struct Explorer {
+    pub cwd: PathBuf,
+    /// Directory uploads are usually made from
+    pub song_dir: PathBuf,
+    pub show_hidden: bool,
+    pub audio_only: bool,
+    pub sort: SortKey,
+}
+
+impl Explorer {
+    pub fn new(song_dir: &str) -> Self {
+        Self {
+            cwd: PathBuf::from(song_dir),
+            song_dir: PathBuf::from(song_dir),
+            show_hidden: false,
+            audio_only: true,
+            sort: SortKey::default(),
+        }
+    }
+
+    /// Filtered and sorted listing of the current directory, directories first.
+    /// `duration` looks up the length of audio files for [`SortKey::Duration`].
+    pub fn list(&self, mut duration: impl FnMut(&Entry) -> Option<Duration>) -> Vec<Entry> {
+        let mut keyed: Vec<(Entry, Option<Duration>)> = get_dir_contents(&self.cwd)
+            .into_iter()
+            .filter(|entry| self.show_hidden || !entry.is_hidden())
+            .filter(|entry| {
+                !self.audio_only || entry.is_dir || entry.is_audio() || entry.is_playlist()
+            })
+            .map(|entry| {
+                let length = match self.sort {
+                    SortKey::Duration if !entry.is_dir => duration(&entry),
+                    _ => None,
+                };
+                (entry, length)
+            })
+            .collect();
+
+        keyed.sort_by(|(a, a_len), (b, b_len)| {
+            let order = match self.sort {
+                SortKey::Name => Ordering::Equal,
+                // Newest, biggest and longest first
+                SortKey::Date => b.modified.cmp(&a.modified),
+                SortKey::Size => b.size.cmp(&a.size),
+                SortKey::Duration => b_len.cmp(a_len),
+            };
+            b.is_dir
+                .cmp(&a.is_dir)
+                .then(order)
+                .then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase()))
+        });
+
+        keyed.into_iter().map(|(entry, _)| entry).collect()
+    }
+
+    pub fn enter(&mut self, dir: &Path) {
+        self.cwd = dir.to_path_buf();
+    }
+
+    /// Moves to the parent directory, returning the one we left.
+    pub fn leave(&mut self) -> Option<PathBuf> {
+        let left = self.cwd.clone();
+        let parent = self.cwd.canonicalize().ok()?.parent()?.to_path_buf();
+        self.cwd = parent;
+        Some(left)
+    }
+
+    pub fn go_home(&mut self) {
+        if let Some(home) = std::env::var_os("HOME") {
+            self.cwd = PathBuf::from(home);
+        }
+    }
+
+    pub fn go_song_dir(&mut self) {
+        self.cwd = self.song_dir.clone();
+    }
+
+    /// "~ › music › album" style path of the current directory.
+    pub fn breadcrumbs(&self) -> String {
+        let cwd = self.cwd.canonicalize().unwrap_or_else(|_| self.cwd.clone());
+        let home = std::env::var_os("HOME").map(PathBuf::from);
+        let (prefix, rest) = match home.as_ref().and_then(|home| cwd.strip_prefix(home).ok()) {
+            Some(rest) => ("~".to_string(), rest.to_path_buf()),
+            None => ("/".to_string(), cwd.strip_prefix("/").unwrap_or(&cwd).to_path_buf()),
+        };
+        std::iter::once(prefix)
+            .chain(rest.iter().map(|part| part.to_string_lossy().to_string()))
+            .collect::<Vec<String>>()
+            .join(" › ")
+    }
+}
@@ -0,0 +1,116 @@
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
+
+/// Row of the filtered view.
+#[derive(Debug, Clone)]
+pub struct Match {
+    /// Position in the unfiltered list
+    pub index: usize,
+    /// Matched character positions in the label, for highlighting
+    pub indices: Vec<usize>,
+}
+
+/// Incremental `/` search over one of the file explorers.
+#[derive(Debug, Clone)]
+pub struct Search {
+    pub query: String,
+    /// Typing goes into the query while editing
+    pub editing: bool,
+    /// Which explorer is filtered
+    /// 0 -> server | 1 -> client
+    pub client: bool,
+    /// Best matches first
+    pub matches: Vec<Match>,
+}
+
+impl Search {
+    pub fn new(client: bool) -> Self {
+        Self {
+            query: String::new(),
+            editing: true,
+            client,
+            matches: vec![],
+        }
+    }
+
+    /// Re-runs the query over the labels of the unfiltered list.
+    pub fn update(&mut self, labels: &[String]) {
+        self.matches = fuzzy_match(labels, &self.query);
+    }
+
+    /// Maps a row of the filtered view back to the unfiltered list.
+    pub fn original_index(&self, row: usize) -> Option<usize> {
+        self.matches.get(row).map(|found| found.index)
+    }
+}
+
+/// Labels matching `query`, best matches first, or all of them in order
+/// for an empty query.
+pub fn fuzzy_match(labels: &[String], query: &str) -> Vec<Match> {
+    if query.is_empty() {
+        return (0..labels.len())
+            .map(|index| Match {
+                index,
+                indices: vec![],
+            })
+            .collect();
+    }
+
+    let matcher = SkimMatcherV2::default().ignore_case();
+    let mut scored: Vec<(i64, Match)> = labels
+        .iter()
+        .enumerate()
+        .filter_map(|(index, label)| {
+            matcher
+                .fuzzy_indices(label, query)
+                .map(|(score, indices)| (score, Match { index, indices }))
+        })
+        .collect();
+    // Stable sort keeps the list order between equal scores
+    scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+    scored.into_iter().map(|(_, found)| found).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(labels: &[&str]) -> Vec<String> {
+        labels.iter().map(|label| label.to_string()).collect()
+    }
+
+    fn order(matches: &[Match]) -> Vec<usize> {
+        matches.iter().map(|found| found.index).collect()
+    }
+
+    #[test]
+    fn empty_query_keeps_everything_in_order() {
+        let labels = labels(&["b.mp3", "a.mp3", "c.wav"]);
+        assert_eq!(order(&fuzzy_match(&labels, "")), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn ranks_closer_matches_first() {
+        let labels = labels(&["r_o_c_k.flac", "jazz.wav", "Rock.mp3"]);
+        let matches = fuzzy_match(&labels, "rock");
+        assert_eq!(order(&matches), vec![2, 0]);
+        assert_eq!(matches[0].indices, vec![0, 1, 2, 3]);
+        assert_eq!(matches[1].indices, vec![0, 2, 4, 6]);
+    }
+
+    #[test]
+    fn equal_scores_keep_the_list_order() {
+        let labels = labels(&["song 2.mp3", "song 1.mp3", "song 3.mp3"]);
+        assert_eq!(order(&fuzzy_match(&labels, "song")), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn maps_rows_back_to_the_list() {
+        let mut search = Search::new(true);
+        search.query = "wav".to_string();
+        search.update(&labels(&["a.mp3", "b.wav", "c.mp3", "d.wav"]));
+        assert_eq!(search.original_index(0), Some(1));
+        assert_eq!(search.original_index(1), Some(3));
+        assert_eq!(search.original_index(2), None);
+    }
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+
+/// Song heard on the radio, kept once it leaves the front of the queue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Name in the server library
+    pub song: String,
+    /// Label at the time, "artist - title" for tagged songs
+    pub title: String,
+    /// Unix time the song came on air, or we tuned in
+    pub started: i64,
+    pub queued_by: String,
+    /// Reaction counts in `REACTIONS` order
+    pub reactions: Vec<u32>,
+}
+
+/// Reads the history log, one JSON entry per line, oldest first.
+pub fn load(path: &str) -> Vec<HistoryEntry> {
+    std::fs::read_to_string(path)
+        .map(|log| {
+            // A line cut short by a crash shouldn't cost the rest
+            log.lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn append(path: &str, entry: &HistoryEntry) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", serde_json::to_string(entry)?)
+}
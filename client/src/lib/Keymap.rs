@@ -0,0 +1,697 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+/// What a key does outside of text input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    /// Clears an active search, quits otherwise
+    Cancel,
+    Up,
+    Down,
+    Top,
+    Bottom,
+    NextPanel,
+    PreviousPanel,
+    /// Enter: upload, enqueue, open, send
+    Activate,
+    Search,
+    FocusChat,
+    VoteSkip,
+    /// Reaction 0 to 4 in `REACTIONS` order
+    React(u8),
+    ToggleSync,
+    ToggleHistory,
+    ToggleLog,
+    Zoom,
+    ExportQueue,
+    Mark,
+    MarkAll,
+    ClearMarks,
+    Download,
+    Favorite,
+    Rate,
+    FavoritesOnly,
+    RandomFavorite,
+    // Queue panel
+    MoveUp,
+    MoveDown,
+    Remove,
+    PlayNext,
+    ClearQueue,
+    Upvote,
+    Downvote,
+    // Local file browser
+    LeaveDir,
+    ToggleHidden,
+    ToggleAudioOnly,
+    CycleSort,
+    GoHome,
+    GoSongDir,
+    ToggleTranscode,
+    Help,
+    // Sync and history views
+    UploadAll,
+    Requeue,
+    // Answers to a question, they only conflict with each other
+    Confirm,
+    Decline,
+}
+
+/// Keys of actions in different scopes never meet, so they may overlap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    /// Panels and the views on top of them
+    Main,
+    /// A question waiting for an answer
+    Prompt,
+}
+
+/// Config names and descriptions of the actions, in the order they are listed.
+pub const ACTIONS: [(Action, &str, &str); 49] = [
+    (Action::Quit, "quit", "Quit"),
+    (Action::Cancel, "cancel", "Clear the search, or quit"),
+    (Action::Up, "up", "Move up"),
+    (Action::Down, "down", "Move down"),
+    (Action::Top, "top", "Jump to the first row"),
+    (Action::Bottom, "bottom", "Jump to the last row"),
+    (Action::NextPanel, "next_panel", "Focus the next panel"),
+    (
+        Action::PreviousPanel,
+        "previous_panel",
+        "Focus the previous panel",
+    ),
+    (
+        Action::Activate,
+        "activate",
+        "Upload / enqueue / open the selected row",
+    ),
+    (Action::Search, "search", "Search the focused explorer"),
+    (Action::FocusChat, "focus_chat", "Write in the chat"),
+    (
+        Action::VoteSkip,
+        "vote_skip",
+        "Vote to skip the current song",
+    ),
+    (Action::React(0), "react_1", "React 🔥"),
+    (Action::React(1), "react_2", "React 💖"),
+    (Action::React(2), "react_3", "React 😂"),
+    (Action::React(3), "react_4", "React 👏"),
+    (Action::React(4), "react_5", "React 😴"),
+    (Action::ToggleSync, "toggle_sync", "Open the sync view"),
+    (
+        Action::ToggleHistory,
+        "toggle_history",
+        "Open the play history",
+    ),
+    (Action::ToggleLog, "toggle_log", "Show the client log"),
+    (Action::Zoom, "zoom", "Maximize the focused panel"),
+    (
+        Action::ExportQueue,
+        "export_queue",
+        "Save the queue (or the history) as M3U",
+    ),
+    (Action::Mark, "mark", "Mark the selected row"),
+    (Action::MarkAll, "mark_all", "Mark every row shown"),
+    (Action::ClearMarks, "clear_marks", "Clear the marks"),
+    (
+        Action::Download,
+        "download",
+        "Server: download the marked or selected songs, sync: all of them",
+    ),
+    (
+        Action::Favorite,
+        "favorite",
+        "Server: favorite the selected song",
+    ),
+    (
+        Action::Rate,
+        "rate",
+        "Server: rate the selected song (1-5 stars)",
+    ),
+    (
+        Action::FavoritesOnly,
+        "favorites_only",
+        "Server: show favorites only",
+    ),
+    (
+        Action::RandomFavorite,
+        "random_favorite",
+        "Enqueue a random favorite",
+    ),
+    (Action::MoveUp, "move_up", "Queue: move the song up"),
+    (Action::MoveDown, "move_down", "Queue: move the song down"),
+    (Action::Remove, "remove", "Queue: remove the song"),
+    (Action::PlayNext, "play_next", "Queue: play the song next"),
+    (Action::ClearQueue, "clear_queue", "Queue: clear the queue"),
+    (Action::Upvote, "upvote", "Queue: upvote the song"),
+    (Action::Downvote, "downvote", "Queue: downvote the song"),
+    (
+        Action::LeaveDir,
+        "leave_dir",
+        "Local: go to the parent directory",
+    ),
+    (
+        Action::ToggleHidden,
+        "toggle_hidden",
+        "Local: show hidden files",
+    ),
+    (
+        Action::ToggleAudioOnly,
+        "toggle_audio_only",
+        "Local: show audio files only",
+    ),
+    (
+        Action::CycleSort,
+        "cycle_sort",
+        "Local: change the sort order",
+    ),
+    (Action::GoHome, "go_home", "Local: go to the home directory"),
+    (
+        Action::GoSongDir,
+        "go_song_dir",
+        "Local: go to the song directory",
+    ),
+    (
+        Action::ToggleTranscode,
+        "toggle_transcode",
+        "Convert uploads to the server format",
+    ),
+    (Action::Help, "help", "Show this help"),
+    (
+        Action::UploadAll,
+        "upload_all",
+        "Sync: upload every local only song",
+    ),
+    (
+        Action::Requeue,
+        "requeue",
+        "History: enqueue the song again",
+    ),
+    (Action::Confirm, "confirm", "Answer yes to a question"),
+    (Action::Decline, "decline", "Answer no to a question"),
+];
+
+impl Action {
+    pub fn name(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .map_or("", |(_, name, _)| name)
+    }
+
+    pub fn describe(self) -> &'static str {
+        ACTIONS
+            .iter()
+            .find(|(action, _, _)| *action == self)
+            .map_or("", |(_, _, description)| description)
+    }
+
+    /// Where the keys of the action are read.
+    pub fn scope(self) -> Scope {
+        match self {
+            Action::Confirm | Action::Decline => Scope::Prompt,
+            _ => Scope::Main,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        ACTIONS
+            .iter()
+            .find(|(_, action_name, _)| *action_name == name)
+            .map(|(action, _, _)| *action)
+    }
+}
+
+const DEFAULT_KEYS: [(&str, &str); 53] = [
+    ("quit", "q"),
+    ("quit", "ctrl-c"),
+    ("cancel", "esc"),
+    ("up", "up"),
+    ("down", "down"),
+    ("top", "home"),
+    ("bottom", "end"),
+    ("next_panel", "tab"),
+    ("previous_panel", "backtab"),
+    ("activate", "enter"),
+    ("search", "/"),
+    ("focus_chat", "i"),
+    ("vote_skip", ">"),
+    ("react_1", "1"),
+    ("react_2", "2"),
+    ("react_3", "3"),
+    ("react_4", "4"),
+    ("react_5", "5"),
+    ("toggle_sync", "S"),
+    ("toggle_history", "H"),
+    ("toggle_log", "L"),
+    ("zoom", "z"),
+    ("export_queue", "E"),
+    ("mark", "space"),
+    ("mark_all", "A"),
+    ("clear_marks", "N"),
+    ("download", "d"),
+    ("favorite", "f"),
+    ("rate", "*"),
+    ("favorites_only", "F"),
+    ("random_favorite", "R"),
+    ("move_up", "shift-up"),
+    ("move_down", "shift-down"),
+    ("remove", "x"),
+    ("remove", "delete"),
+    ("play_next", "n"),
+    ("clear_queue", "c"),
+    ("upvote", "+"),
+    ("upvote", "="),
+    ("downvote", "-"),
+    ("leave_dir", "backspace"),
+    ("leave_dir", "left"),
+    ("toggle_hidden", "."),
+    ("toggle_audio_only", "a"),
+    ("cycle_sort", "s"),
+    ("go_home", "~"),
+    ("go_song_dir", "`"),
+    ("toggle_transcode", "t"),
+    ("help", "?"),
+    ("upload_all", "u"),
+    ("requeue", "r"),
+    ("confirm", "y"),
+    ("decline", "n"),
+];
+
+// Presets add to the default keys
+const VIM_KEYS: [(&str, &str); 6] = [
+    ("down", "j"),
+    ("up", "k"),
+    ("top", "g g"),
+    ("bottom", "G"),
+    ("leave_dir", "h"),
+    ("activate", "l"),
+];
+
+const EMACS_KEYS: [(&str, &str); 8] = [
+    ("down", "ctrl-n"),
+    ("up", "ctrl-p"),
+    ("top", "alt-<"),
+    ("bottom", "alt->"),
+    ("quit", "ctrl-x ctrl-c"),
+    ("cancel", "ctrl-g"),
+    ("search", "ctrl-s"),
+    ("leave_dir", "ctrl-b"),
+];
+
+/// A key with its modifiers. Shift is part of the character for
+/// character keys, so `G` and `shift-g` are the same key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub code: KeyCode,
+    pub modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Self {
+        let mut modifiers = event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        // Terminals report backtab as shift-backtab
+        if !matches!(event.code, KeyCode::Char(_) | KeyCode::BackTab) {
+            modifiers |= event.modifiers & KeyModifiers::SHIFT;
+        }
+        Self {
+            code: event.code,
+            modifiers,
+        }
+    }
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 16] = [
+    ("esc", KeyCode::Esc),
+    ("enter", KeyCode::Enter),
+    ("tab", KeyCode::Tab),
+    ("backtab", KeyCode::BackTab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("space", KeyCode::Char(' ')),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+];
+
+impl Key {
+    /// "q", "G", "ctrl-c", "alt->", "shift-up", "f5", "space"
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rest = text;
+        let mut modifiers = KeyModifiers::NONE;
+        // A lone "-" is the minus key, not a modifier separator
+        while rest.len() > 1 {
+            if let Some(after) = rest.strip_prefix("ctrl-") {
+                modifiers |= KeyModifiers::CONTROL;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("alt-") {
+                modifiers |= KeyModifiers::ALT;
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("shift-") {
+                modifiers |= KeyModifiers::SHIFT;
+                rest = after;
+            } else {
+                break;
+            }
+        }
+
+        let mut characters = rest.chars();
+        let code = match (characters.next(), characters.next()) {
+            (Some(character), None) if modifiers.contains(KeyModifiers::SHIFT) => {
+                modifiers.remove(KeyModifiers::SHIFT);
+                KeyCode::Char(character.to_ascii_uppercase())
+            }
+            (Some(character), None) => KeyCode::Char(character),
+            _ => match NAMED_KEYS.iter().find(|(name, _)| *name == rest) {
+                Some((_, code)) => *code,
+                None => match rest.strip_prefix('f').and_then(|n| n.parse().ok()) {
+                    Some(number) => KeyCode::F(number),
+                    None => return Err(format!("unknown key \"{}\"", text)),
+                },
+            },
+        };
+        Ok(Self { code, modifiers })
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "alt-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "shift-")?;
+        }
+        match NAMED_KEYS.iter().find(|(_, code)| *code == self.code) {
+            Some((name, _)) => write!(f, "{}", name),
+            None => match self.code {
+                KeyCode::Char(character) => write!(f, "{}", character),
+                KeyCode::F(number) => write!(f, "f{}", number),
+                code => write!(f, "{:?}", code),
+            },
+        }
+    }
+}
+
+/// Key sequence separated by spaces, e.g. "g g" or "ctrl-x ctrl-c".
+fn parse_sequence(text: &str) -> Result<Vec<Key>, String> {
+    let keys = text
+        .split_whitespace()
+        .map(Key::parse)
+        .collect::<Result<Vec<Key>, String>>()?;
+    if keys.is_empty() {
+        return Err("empty key sequence".to_string());
+    }
+    Ok(keys)
+}
+
+pub fn describe_sequence(keys: &[Key]) -> String {
+    keys.iter()
+        .map(Key::to_string)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Result of looking up the keys pressed so far.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Lookup {
+    Action(Action),
+    /// Start of a longer sequence, waiting for the next key
+    Pending,
+    Unbound,
+}
+
+/// The keymap file: a preset plus bindings replacing the keys of single actions.
+///
+/// `{"preset": "vim", "bindings": {"remove": ["x", "d d"], "quit": ["ctrl-q"]}}`
+#[derive(Debug, Default, Deserialize)]
+struct KeymapConfig {
+    #[serde(default)]
+    preset: Option<String>,
+    #[serde(default)]
+    bindings: HashMap<String, Vec<String>>,
+}
+
+/// Key sequences bound to actions.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Preset the keymap was built from
+    pub preset: String,
+    /// In listing order
+    pub bindings: Vec<(Vec<Key>, Action)>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset("default").unwrap()
+    }
+}
+
+impl Keymap {
+    /// The default keys plus those of the named preset.
+    pub fn preset(name: &str) -> Result<Self, String> {
+        let extra: &[(&str, &str)] = match name {
+            "default" => &[],
+            "vim" => &VIM_KEYS,
+            "emacs" => &EMACS_KEYS,
+            _ => return Err(format!("unknown preset \"{}\"", name)),
+        };
+        let mut bindings = vec![];
+        for (action, keys) in DEFAULT_KEYS.iter().chain(extra) {
+            // Built in tables, a typo here is a bug
+            let action = Action::from_name(action).expect("bound action exists");
+            bindings.push((parse_sequence(keys)?, action));
+        }
+        Ok(Self {
+            preset: name.to_string(),
+            bindings,
+        })
+    }
+
+    /// Reads a keymap file, the default keymap when there is none.
+    /// Any problem, conflicts included, rejects the whole file.
+    pub fn load(path: &str) -> Result<Self, Vec<String>> {
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return Ok(Self::default());
+        };
+        Self::parse(&content)
+    }
+
+    /// Keymap of a keymap file's content.
+    fn parse(content: &str) -> Result<Self, Vec<String>> {
+        let config: KeymapConfig =
+            serde_json::from_str(content).map_err(|err| vec![err.to_string()])?;
+        let mut keymap =
+            Self::preset(config.preset.as_deref().unwrap_or("default")).map_err(|err| vec![err])?;
+
+        let mut problems = vec![];
+        let mut names: Vec<&String> = config.bindings.keys().collect();
+        names.sort();
+        for name in names {
+            let Some(action) = Action::from_name(name) else {
+                problems.push(format!("unknown action \"{}\"", name));
+                continue;
+            };
+            keymap.bindings.retain(|(_, bound)| *bound != action);
+            for keys in &config.bindings[name] {
+                match parse_sequence(keys) {
+                    Ok(keys) => keymap.bindings.push((keys, action)),
+                    Err(err) => problems.push(format!("{}: {}", name, err)),
+                }
+            }
+        }
+        problems.extend(keymap.conflicts());
+
+        if problems.is_empty() {
+            Ok(keymap)
+        } else {
+            Err(problems)
+        }
+    }
+
+    /// Sequences bound twice, or that start a longer sequence and would
+    /// never let it be typed.
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut conflicts = vec![];
+        for (position, (keys, action)) in self.bindings.iter().enumerate() {
+            for (other_keys, other_action) in &self.bindings[position + 1..] {
+                let (shorter, longer) = if keys.len() <= other_keys.len() {
+                    (keys, other_keys)
+                } else {
+                    (other_keys, keys)
+                };
+                if !longer.starts_with(shorter)
+                    || (keys == other_keys && action == other_action)
+                    || action.scope() != other_action.scope()
+                {
+                    continue;
+                }
+                conflicts.push(if keys == other_keys {
+                    format!(
+                        "\"{}\" is bound to both {} and {}",
+                        describe_sequence(keys),
+                        action.name(),
+                        other_action.name()
+                    )
+                } else {
+                    format!(
+                        "\"{}\" hides \"{}\"",
+                        describe_sequence(shorter),
+                        describe_sequence(longer)
+                    )
+                });
+            }
+        }
+        conflicts
+    }
+
+    pub fn lookup(&self, pressed: &[Key]) -> Lookup {
+        self.lookup_in(Scope::Main, pressed)
+    }
+
+    fn lookup_in(&self, scope: Scope, pressed: &[Key]) -> Lookup {
+        let mut lookup = Lookup::Unbound;
+        for (keys, action) in &self.bindings {
+            if action.scope() != scope {
+                continue;
+            }
+            if keys == pressed {
+                return Lookup::Action(*action);
+            }
+            if keys.starts_with(pressed) {
+                lookup = Lookup::Pending;
+            }
+        }
+        lookup
+    }
+
+    /// Action of a single key, for views that don't take sequences.
+    pub fn action(&self, key: Key) -> Option<Action> {
+        match self.lookup(&[key]) {
+            Lookup::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    /// Answer given with a single key while a question is asked.
+    pub fn answer(&self, key: Key) -> Option<Action> {
+        match self.lookup_in(Scope::Prompt, &[key]) {
+            Lookup::Action(action) => Some(action),
+            _ => None,
+        }
+    }
+
+    /// Sequences bound to an action, in listing order.
+    pub fn keys(&self, action: Action) -> Vec<String> {
+        self.bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(keys, _)| describe_sequence(keys))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(text: &str) -> Key {
+        Key::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parses_keys() {
+        assert_eq!(key("q").code, KeyCode::Char('q'));
+        assert_eq!(key("shift-g"), key("G"));
+        assert_eq!(key("-").code, KeyCode::Char('-'));
+        assert_eq!(key("f5").code, KeyCode::F(5));
+        assert_eq!(key("space").code, KeyCode::Char(' '));
+        let ctrl_c = key("ctrl-c");
+        assert_eq!(ctrl_c.code, KeyCode::Char('c'));
+        assert_eq!(ctrl_c.modifiers, KeyModifiers::CONTROL);
+        assert_eq!(key("alt->").modifiers, KeyModifiers::ALT);
+        assert!(Key::parse("hyper-q").is_err());
+        assert!(parse_sequence(" ").is_err());
+    }
+
+    #[test]
+    fn keys_read_back_as_written() {
+        for text in ["q", "G", "ctrl-c", "alt->", "shift-up", "f5", "space", "-"] {
+            assert_eq!(key(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn presets_have_no_conflicts() {
+        for name in ["default", "vim", "emacs"] {
+            let keymap = Keymap::preset(name).unwrap();
+            assert_eq!(keymap.conflicts(), Vec::<String>::new(), "{}", name);
+        }
+        assert!(Keymap::preset("nano").is_err());
+    }
+
+    #[test]
+    fn presets_add_to_the_default_keys() {
+        let vim = Keymap::preset("vim").unwrap();
+        assert_eq!(vim.action(key("j")), Some(Action::Down));
+        assert_eq!(vim.action(key("down")), Some(Action::Down));
+        assert_eq!(vim.lookup(&[key("g")]), Lookup::Pending);
+        assert_eq!(
+            vim.lookup(&[key("g"), key("g")]),
+            Lookup::Action(Action::Top)
+        );
+        assert_eq!(Keymap::default().action(key("j")), None);
+    }
+
+    #[test]
+    fn answers_only_use_prompt_keys() {
+        let keymap = Keymap::default();
+        assert_eq!(keymap.action(key("n")), Some(Action::PlayNext));
+        assert_eq!(keymap.answer(key("n")), Some(Action::Decline));
+        assert_eq!(keymap.answer(key("y")), Some(Action::Confirm));
+        assert_eq!(keymap.answer(key("q")), None);
+    }
+
+    #[test]
+    fn bindings_replace_the_keys_of_an_action() {
+        let keymap =
+            Keymap::parse(r#"{"preset": "vim", "bindings": {"remove": ["X", "ctrl-d"]}}"#).unwrap();
+        assert_eq!(keymap.keys(Action::Remove), vec!["X", "ctrl-d"]);
+        assert_eq!(keymap.action(key("x")), None);
+    }
+
+    #[test]
+    fn rejects_conflicting_bindings() {
+        let problems = Keymap::parse(r#"{"bindings": {"quit": ["d"]}}"#).unwrap_err();
+        assert_eq!(problems, vec![r#""d" is bound to both download and quit"#]);
+        let problems = Keymap::parse(r#"{"bindings": {"help": ["f f"]}}"#).unwrap_err();
+        assert_eq!(problems, vec![r#""f" hides "f f""#]);
+    }
+
+    #[test]
+    fn reports_every_problem() {
+        let problems =
+            Keymap::parse(r#"{"bindings": {"fly": ["w"], "help": ["hyper-h"]}}"#).unwrap_err();
+        assert_eq!(
+            problems,
+            vec![
+                r#"unknown action "fly""#.to_string(),
+                r#"help: unknown key "hyper-h""#.to_string(),
+            ]
+        );
+        assert!(Keymap::parse(r#"{"preset": "nano"}"#).is_err());
+        assert!(Keymap::parse("not json").is_err());
+    }
+}
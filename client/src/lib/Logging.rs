@@ -0,0 +1,134 @@
+use std::collections::VecDeque;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{RollingFileAppender, Rotation},
+};
+use tracing_subscriber::{
+    filter::LevelFilter,
+    layer::{Context, SubscriberExt},
+    util::SubscriberInitExt,
+    Layer,
+};
+
+// Entries kept in memory for the log viewer
+const RECENT_ENTRIES: usize = 500;
+// Daily log files kept around, older ones are deleted
+const LOG_FILES: usize = 7;
+
+/// Entry shown in the log viewer.
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: String,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+}
+
+/// Latest entries, shared by the subscriber and the app.
+#[derive(Debug, Clone, Default)]
+pub struct RecentLog(Arc<Mutex<VecDeque<LogEntry>>>);
+
+impl RecentLog {
+    /// Oldest first.
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.0
+            .lock()
+            .map(|entries| entries.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for RecentLog {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut message = Message::default();
+        event.record(&mut message);
+        let entry = LogEntry {
+            time: chrono::Local::now().format("%H:%M:%S").to_string(),
+            level: *event.metadata().level(),
+            target: message
+                .target
+                .unwrap_or_else(|| event.metadata().target().to_string()),
+            message: message.text,
+        };
+        if let Ok(mut entries) = self.0.lock() {
+            if entries.len() == RECENT_ENTRIES {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+    }
+}
+
+/// The message of an event followed by its other fields, "key=value".
+#[derive(Default)]
+struct Message {
+    text: String,
+    /// Where records of the `log` crate (from dependencies) came from
+    target: Option<String>,
+}
+
+impl Visit for Message {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "log.target" {
+            self.target = Some(value.to_string());
+        } else {
+            self.record_debug(field, &value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        // The rest of a `log` record's metadata is noise here
+        if field.name().starts_with("log.") {
+            return;
+        }
+        if !self.text.is_empty() {
+            self.text.push(' ');
+        }
+        let _ = if field.name() == "message" {
+            write!(self.text, "{:?}", value)
+        } else {
+            write!(self.text, "{}={:?}", field.name(), value)
+        };
+    }
+}
+
+/// Level set with `JAM_LOG` ("error" to "trace", or "off"), info by default.
+pub fn level() -> Result<LevelFilter, String> {
+    match std::env::var("JAM_LOG") {
+        Ok(level) => LevelFilter::from_str(&level)
+            .map_err(|_| format!("JAM_LOG: unknown level \"{}\"", level)),
+        Err(_) => Ok(LevelFilter::INFO),
+    }
+}
+
+/// Logs to daily rotated files in `dir` and keeps the latest entries for the
+/// log viewer. Entries still buffered are written when the guard is dropped.
+pub fn init(dir: &str, level: LevelFilter) -> Result<(WorkerGuard, RecentLog), String> {
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("jam_client")
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES)
+        .build(dir)
+        .map_err(|err| err.to_string())?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+    let recent = RecentLog::default();
+    tracing_subscriber::registry()
+        .with(level)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(writer)
+                .with_ansi(false),
+        )
+        .with(recent.clone())
+        .try_init()
+        .map_err(|err| err.to_string())?;
+    Ok((guard, recent))
+}
@@ -1,8 +1,22 @@
 use crate::app::{App, AppResult};
-use std::sync::{Arc, Mutex};
+use crate::lib::{AudioFile::ProbeInfo, Codec::supported_codecs};
+use std::sync::Arc;
 use tokio::fs::File;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::Mutex;
+
+// Where the server keeps its song library
+const SERVER_SONG_DIR: &str = "./songs/";
+
+/// Library path a local file is uploaded to, wherever it was browsed from.
+pub fn server_song_path(file_path: &str) -> String {
+    let file_name = std::path::Path::new(file_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    format!("{}{}", SERVER_SONG_DIR, file_name)
+}
 
 // SongTransfer protocol [signature 'f' at buf[0]]
 // filename_size -> 4B
@@ -12,7 +26,21 @@ use tokio::net::TcpStream;
 
 #[allow(non_snake_case)]
 pub async fn sendSong<'a>(file_path: String, app: &mut App<'a>) -> AppResult<()> {
-    let stream: Arc<Mutex<TcpStream>> = app.c_connection.clone().unwrap();
+    // audio_file
+    let mut file = File::open(&file_path).await?;
+    let mut buffer: Vec<u8> = Vec::new();
+    file.read_to_end(&mut buffer).await?;
+
+    sendSongData(server_song_path(&file_path), buffer, app).await
+}
+
+/// Uploads audio that is already in memory (e.g. a transcoded file).
+pub async fn sendSongData<'a>(
+    file_path: String,
+    buffer: Vec<u8>,
+    app: &mut App<'a>,
+) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
 
     // Initialize message with signature
     let mut message: Vec<u8> = "f".into();
@@ -21,12 +49,7 @@ pub async fn sendSong<'a>(file_path: String, app: &mut App<'a>) -> AppResult<()>
     let filename_size = file_path.len() as u32; // 4B
 
     // filename
-    let path_buffer: Vec<u8> = file_path.clone().into();
-
-    // audio_file
-    let mut file = File::open(file_path).await?;
-    let mut buffer: Vec<u8> = Vec::new();
-    file.read_to_end(&mut buffer).await?;
+    let path_buffer: Vec<u8> = file_path.into();
 
     // audio_file_size
     let file_size = buffer.len() as u32; // 4B
@@ -37,18 +60,58 @@ pub async fn sendSong<'a>(file_path: String, app: &mut App<'a>) -> AppResult<()>
     message.extend(&file_size.to_be_bytes());
     message.extend(buffer);
 
-    stream.lock().unwrap().write_all(&message).await?;
+    stream.lock().await.write_all(&message).await?;
+
+    Ok(())
+}
+
+// SongMetadata protocol [signature 'm' at buf[0]], sent after the upload
+// filename_size -> 4B
+// filename -> var
+// artist_size -> 4B
+// artist -> var
+// title_size -> 4B
+// title -> var
+// album_size -> 4B
+// album -> var
+// length -> 4B (seconds)
+
+pub async fn sendMetadata<'a>(
+    file_path: String,
+    probe: &ProbeInfo,
+    app: &mut App<'a>,
+) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
+
+    let mut message: Vec<u8> = "m".into();
+
+    let fields = [
+        Some(file_path),
+        probe.artist.clone(),
+        probe.title.clone(),
+        probe.album.clone(),
+    ];
+    for field in fields {
+        let field = field.unwrap_or_default();
+        message.extend(&(field.len() as u32).to_be_bytes());
+        message.extend(field.into_bytes());
+    }
+
+    let length = probe.duration.map_or(0, |duration| duration.as_secs() as u32);
+    message.extend(&length.to_be_bytes());
+
+    stream.lock().await.write_all(&message).await?;
 
     Ok(())
 }
 
 pub async fn toQueue<'a>(song_name: String, app: &mut App<'a>) -> AppResult<()> {
-    let stream: Arc<Mutex<TcpStream>> = app.c_connection.clone().unwrap();
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
 
     let mut message: Vec<u8> = "q".into();
 
     let mut songname = song_name.clone();
-    songname.insert_str(0, "./songs/");
+    songname.insert_str(0, SERVER_SONG_DIR);
 
     // songname_size
     let songname_size = songname.len() as u32;
@@ -58,7 +121,183 @@ pub async fn toQueue<'a>(song_name: String, app: &mut App<'a>) -> AppResult<()>
     message.extend(&songname_size.to_be_bytes());
     message.extend(buf);
 
-    stream.lock().unwrap().write_all(&message).await?;
+    stream.lock().await.write_all(&message).await?;
+
+    Ok(())
+}
+
+// Handshake protocol [signature 'h' at buf[0]]
+// codecs_size -> 4B
+// codecs -> var (comma separated, most preferred first)
+// bitrate -> 4B (bits per second, 0 -> server default)
+
+pub async fn sendHandshake<'a>(app: &mut App<'a>) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
+
+    let mut message: Vec<u8> = "h".into();
+
+    let codecs = supported_codecs()
+        .iter()
+        .map(|codec| codec.name())
+        .collect::<Vec<&str>>()
+        .join(",");
+
+    // codecs_size
+    let codecs_size = codecs.len() as u32;
+
+    let buf: Vec<u8> = codecs.into();
+
+    message.extend(&codecs_size.to_be_bytes());
+    message.extend(buf);
+    message.extend(&app.bitrate.to_be_bytes());
+
+    stream.lock().await.write_all(&message).await?;
+
+    Ok(())
+}
+
+// Nickname protocol [signature 'n' at buf[0]], sent after the handshake
+// nickname_size -> 4B
+// nickname -> var
+
+pub async fn sendNickname<'a>(app: &mut App<'a>) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
+
+    let mut message: Vec<u8> = "n".into();
+
+    let nickname: Vec<u8> = app.nickname.clone().into();
+
+    message.extend(&(nickname.len() as u32).to_be_bytes());
+    message.extend(nickname);
+
+    stream.lock().await.write_all(&message).await?;
+
+    Ok(())
+}
+
+// Download protocol [signature 'd' at buf[0]]
+// songname_size -> 4B
+// songname -> var (name in the server library)
+// The server answers with a JSON announcement and base64 chunks
+
+pub async fn requestDownload<'a>(song_name: String, app: &mut App<'a>) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
+
+    let mut message: Vec<u8> = "d".into();
+
+    let song_name: Vec<u8> = song_name.into();
+
+    message.extend(&(song_name.len() as u32).to_be_bytes());
+    message.extend(song_name);
+
+    stream.lock().await.write_all(&message).await?;
+
+    Ok(())
+}
+
+// Reaction protocol [signature 'r' at buf[0]]
+// kind -> 1B (index into REACTIONS)
+
+/// Reactions to the current song, in protocol order
+pub const REACTIONS: [&str; 5] = ["🔥", "💖", "😂", "👏", "😴"];
+
+pub async fn sendReaction<'a>(kind: u8, app: &mut App<'a>) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
+
+    let message: Vec<u8> = vec![b'r', kind];
+
+    stream.lock().await.write_all(&message).await?;
+
+    Ok(())
+}
+
+// Chat protocol [signature 'c' at buf[0]]
+// text_size -> 4B
+// text -> var
+
+pub async fn sendChat<'a>(text: String, app: &mut App<'a>) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
+
+    let mut message: Vec<u8> = "c".into();
+
+    let text: Vec<u8> = text.into();
+
+    message.extend(&(text.len() as u32).to_be_bytes());
+    message.extend(text);
+
+    stream.lock().await.write_all(&message).await?;
+
+    Ok(())
+}
+
+// QueueEdit protocol [signature 'e' at buf[0]]
+// operation -> 1B
+// queue_index -> 4B (0 is the song currently playing)
+
+#[derive(Debug, Clone, Copy)]
+pub enum QueueEdit {
+    Remove,
+    MoveUp,
+    MoveDown,
+    PlayNext,
+    Clear,
+}
+
+impl QueueEdit {
+    fn operation(&self) -> u8 {
+        match self {
+            QueueEdit::Remove => b'r',
+            QueueEdit::MoveUp => b'u',
+            QueueEdit::MoveDown => b'd',
+            QueueEdit::PlayNext => b'n',
+            QueueEdit::Clear => b'c',
+        }
+    }
+}
+
+pub async fn editQueue<'a>(edit: QueueEdit, queue_index: u32, app: &mut App<'a>) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
+
+    let mut message: Vec<u8> = "e".into();
+
+    message.push(edit.operation());
+    message.extend(&queue_index.to_be_bytes());
+
+    stream.lock().await.write_all(&message).await?;
+
+    Ok(())
+}
+
+// Vote protocol [signature 'v' at buf[0]]
+// kind -> 1B
+// queue_index -> 4B (ignored for skip votes, which target the current song)
+
+#[derive(Debug, Clone, Copy)]
+pub enum Vote {
+    Skip,
+    Up,
+    Down,
+}
+
+impl Vote {
+    fn kind(&self) -> u8 {
+        match self {
+            Vote::Skip => b's',
+            Vote::Up => b'+',
+            Vote::Down => b'-',
+        }
+    }
+}
+
+pub async fn sendVote<'a>(vote: Vote, queue_index: u32, app: &mut App<'a>) -> AppResult<()> {
+    let stream: Arc<Mutex<OwnedWriteHalf>> = app.c_connection.clone().unwrap();
+
+    let mut message: Vec<u8> = "v".into();
+
+    message.push(vote.kind());
+    message.extend(&queue_index.to_be_bytes());
+
+    stream.lock().await.write_all(&message).await?;
 
     Ok(())
 }
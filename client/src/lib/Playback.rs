@@ -1,34 +1,48 @@
+use crate::lib::Codec::{AudioPacket, OpusDecoder, OPUS_CHANNELS, OPUS_SAMPLE_RATE};
+use rodio::buffer::SamplesBuffer;
 use rodio::{Decoder, Sink};
 use std::collections::VecDeque;
 use std::io::Cursor;
 use std::sync::Arc;
-use std::u8;
 use tokio::sync::{mpsc, Mutex};
-use tokio::time::{sleep, Duration};
+use tracing::{debug, info, warn};
 
-pub async fn playback_audio(rx: Arc<Mutex<mpsc::Receiver<Vec<u8>>>>, sink: Arc<Sink>) {
+pub async fn playback_audio(rx: Arc<Mutex<mpsc::Receiver<AudioPacket>>>, sink: Arc<Sink>) {
     let mut playback_buffer: VecDeque<Vec<u8>> = VecDeque::new();
+    let mut opus_decoder = OpusDecoder::new();
     let min_buf = 2;
+    info!(opus = opus_decoder.is_some(), "playback started");
     loop {
         let chunk = rx.lock().await.recv().await;
         match chunk {
-            Some(data) => {
+            Some(AudioPacket::Raw(data)) => {
                 playback_buffer.push_back(data);
                 if playback_buffer.len() >= min_buf {
                     let audio_chunk = playback_buffer.pop_front();
-                    match audio_chunk {
-                        Some(ac) => {
-                            let cursor = Cursor::new(ac);
-                            if let Ok(source) = Decoder::new(cursor) {
-                                sink.append(source);
-                            }
+                    if let Some(ac) = audio_chunk {
+                        let cursor = Cursor::new(ac);
+                        match Decoder::new(cursor) {
+                            Ok(source) => sink.append(source),
+                            Err(err) => warn!("dropped an undecodable chunk: {}", err),
                         }
-                        None => {}
                     }
                 }
             }
+            Some(AudioPacket::Opus(packet)) => {
+                // Opus packets are self contained, decode them straight into the sink
+                match opus_decoder
+                    .as_mut()
+                    .and_then(|decoder| decoder.decode(&packet))
+                {
+                    Some(samples) => {
+                        sink.append(SamplesBuffer::new(OPUS_CHANNELS, OPUS_SAMPLE_RATE, samples))
+                    }
+                    None => debug!(bytes = packet.len(), "dropped an opus packet"),
+                }
+            }
             None => {
                 // tx was shutdown kill thread
+                info!("playback stopped");
                 break;
             }
         }
@@ -0,0 +1,140 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const PLAYLIST_EXTENSIONS: [&str; 3] = ["m3u", "m3u8", "pls"];
+
+pub fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| PLAYLIST_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Song locations of a playlist in order, relative ones resolved
+/// against the playlist's directory.
+pub fn read(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let content = fs::read_to_string(path)?;
+    let is_pls = path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pls"));
+    Ok(parse(
+        &content,
+        is_pls,
+        path.parent().unwrap_or(Path::new(".")),
+    ))
+}
+
+fn parse(content: &str, is_pls: bool, base: &Path) -> Vec<PathBuf> {
+    let locations: Vec<&str> = if is_pls {
+        // [playlist] / File1=... / Title1=... / NumberOfEntries=...
+        let mut files: Vec<(u32, &str)> = content
+            .lines()
+            .filter_map(|line| {
+                let (key, value) = line.trim().split_once('=')?;
+                let number = key.strip_prefix("File")?.parse().ok()?;
+                Some((number, value.trim()))
+            })
+            // "File2=" names nothing, not the playlist's directory
+            .filter(|(_, file)| !file.is_empty())
+            .collect();
+        files.sort_by_key(|(number, _)| *number);
+        files.into_iter().map(|(_, file)| file).collect()
+    } else {
+        // Everything but comments and #EXT directives is a location
+        content
+            .lines()
+            .map(|line| line.trim().trim_start_matches('\u{feff}'))
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect()
+    };
+
+    locations
+        .into_iter()
+        .map(|location| {
+            let location = location.strip_prefix("file://").unwrap_or(location);
+            base.join(location)
+        })
+        .collect()
+}
+
+/// One line of an exported playlist.
+#[derive(Debug, Clone)]
+pub struct PlaylistEntry {
+    pub location: String,
+    /// "artist - title", falls back to the location
+    pub title: String,
+    /// Seconds, -1 when unknown
+    pub length: i64,
+}
+
+/// Extended M3U, readable by most players and by [`read`].
+pub fn write_m3u(path: &Path, entries: &[PlaylistEntry]) -> io::Result<()> {
+    let mut content = String::from("#EXTM3U\n");
+    for entry in entries {
+        content.push_str(&format!("#EXTINF:{},{}\n", entry.length, entry.title));
+        content.push_str(&entry.location);
+        content.push('\n');
+    }
+    fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn recognizes_playlists() {
+        assert!(is_playlist(Path::new("mix.m3u")));
+        assert!(is_playlist(Path::new("mix.M3U8")));
+        assert!(is_playlist(Path::new("mix.pls")));
+        assert!(!is_playlist(Path::new("mix.mp3")));
+        assert!(!is_playlist(Path::new("m3u")));
+    }
+
+    #[test]
+    fn reads_m3u() {
+        let content = "\u{feff}#EXTM3U\n\
+            #EXTINF:215,Artist - Song\n\
+            song.mp3\n\
+            \n\
+            sub/other.wav\n\
+            # a comment\n\
+            /music/absolute.flac\n\
+            file:///music/uri.mp3\n";
+        assert_eq!(
+            parse(content, false, Path::new("/lists")),
+            paths(&[
+                "/lists/song.mp3",
+                "/lists/sub/other.wav",
+                "/music/absolute.flac",
+                "/music/uri.mp3",
+            ])
+        );
+    }
+
+    #[test]
+    fn reads_pls_in_entry_order() {
+        let content = "[playlist]\n\
+            File2=/music/second.mp3\n\
+            Title2=Second\n\
+            File1=first.mp3\n\
+            File3=\n\
+            File5= last.wav \n\
+            NumberOfEntries=5\n\
+            Version=2\n";
+        assert_eq!(
+            parse(content, true, Path::new("/lists")),
+            paths(&["/lists/first.mp3", "/music/second.mp3", "/lists/last.wav"])
+        );
+    }
+
+    #[test]
+    fn empty_playlists_have_no_songs() {
+        assert!(parse("#EXTM3U\n", false, Path::new(".")).is_empty());
+        assert!(parse("[playlist]\nNumberOfEntries=0\n", true, Path::new(".")).is_empty());
+    }
+}
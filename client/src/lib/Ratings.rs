@@ -0,0 +1,63 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+pub const MAX_STARS: u8 = 5;
+
+/// Favorites and star ratings given to the songs of one server.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Ratings {
+    #[serde(default)]
+    pub favorites: HashSet<String>,
+    /// 1 to `MAX_STARS`, unrated songs have no entry
+    #[serde(default)]
+    pub stars: HashMap<String, u8>,
+}
+
+impl Ratings {
+    pub fn is_favorite(&self, song: &str) -> bool {
+        self.favorites.contains(song)
+    }
+
+    pub fn toggle_favorite(&mut self, song: &str) {
+        if !self.favorites.remove(song) {
+            self.favorites.insert(song.to_string());
+        }
+    }
+
+    pub fn stars(&self, song: &str) -> u8 {
+        self.stars.get(song).copied().unwrap_or(0)
+    }
+
+    /// 0 -> 1 -> ... -> `MAX_STARS` -> unrated
+    pub fn cycle_stars(&mut self, song: &str) {
+        match self.stars(song) {
+            MAX_STARS => {
+                self.stars.remove(song);
+            }
+            stars => {
+                self.stars.insert(song.to_string(), stars + 1);
+            }
+        }
+    }
+}
+
+// The file holds one profile per server, keyed by "host:port"
+type Profiles = HashMap<String, Ratings>;
+
+fn load_profiles(path: &str) -> Profiles {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|profiles| serde_json::from_str(&profiles).ok())
+        .unwrap_or_default()
+}
+
+pub fn load(path: &str, server: &str) -> Ratings {
+    load_profiles(path).remove(server).unwrap_or_default()
+}
+
+/// Replaces the profile of `server`, keeping the other servers' ones.
+pub fn save(path: &str, server: &str, ratings: &Ratings) -> std::io::Result<()> {
+    let mut profiles = load_profiles(path);
+    profiles.insert(server.to_string(), ratings.clone());
+    std::fs::write(path, serde_json::to_string(&profiles)?)
+}
@@ -1,4 +1,4 @@
-use rodio::{OutputStream, Sink, Source};
+use rodio::Source;
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -7,6 +7,12 @@ pub struct RawAudioSource {
     pub position: usize,            // Current read position in the buffer
 }
 
+impl Default for RawAudioSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl RawAudioSource {
     pub fn new() -> Self {
         RawAudioSource {
@@ -40,7 +46,7 @@ impl Clone for RawAudioSource {
     fn clone(&self) -> Self {
         RawAudioSource {
             data: Arc::clone(&self.data),
-            position: self.position.clone(),
+            position: self.position,
         }
     }
 }
@@ -0,0 +1,211 @@
+use crate::lib::{Download::hash_file, FileExplorer::get_dir_contents};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Where a song of the sync view lives.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncStatus {
+    LocalOnly,
+    ServerOnly,
+    /// Same name and content on both sides
+    Same,
+    /// Same name, different content
+    Differs,
+    /// Same content under another name on the other side
+    Renamed(String),
+}
+
+impl SyncStatus {
+    pub fn describe(&self) -> String {
+        match self {
+            SyncStatus::LocalOnly => "↑ local only".to_string(),
+            SyncStatus::ServerOnly => "↓ server only".to_string(),
+            SyncStatus::Same => "= in sync".to_string(),
+            SyncStatus::Differs => "≠ differs".to_string(),
+            SyncStatus::Renamed(other) => format!("≈ same as {}", other),
+        }
+    }
+}
+
+/// Name, path and content hash of a song in the local song directory.
+pub type LocalSong = (String, PathBuf, String);
+
+#[derive(Debug, Clone)]
+pub struct SyncEntry {
+    pub name: String,
+    pub status: SyncStatus,
+    /// Set for songs that exist locally
+    pub path: Option<PathBuf>,
+}
+
+/// Hashes of the local song directory, kept between refreshes by mtime.
+#[derive(Debug, Clone, Default)]
+pub struct LocalHashes {
+    hashes: HashMap<PathBuf, (Option<SystemTime>, String)>,
+}
+
+impl LocalHashes {
+    /// Audio files of `dir` with their content hash.
+    pub fn scan(&mut self, dir: &Path) -> Vec<LocalSong> {
+        let mut songs = vec![];
+        for entry in get_dir_contents(dir) {
+            if entry.is_dir || entry.is_hidden() || !entry.is_audio() {
+                continue;
+            }
+            let hash = match self.hashes.get(&entry.path) {
+                Some((modified, hash)) if *modified == entry.modified => hash.clone(),
+                _ => {
+                    let Ok(hash) = hash_file(&entry.path) else {
+                        continue;
+                    };
+                    self.hashes
+                        .insert(entry.path.clone(), (entry.modified, hash.clone()));
+                    hash
+                }
+            };
+            songs.push((entry.name, entry.path, hash));
+        }
+        songs
+    }
+}
+
+/// Diffs the local songs against the server library, by name and by hash.
+pub fn diff(
+    local: &[LocalSong],
+    library: &[String],
+    server_hashes: &HashMap<String, String>,
+) -> Vec<SyncEntry> {
+    let local_by_name: HashMap<&str, &str> = local
+        .iter()
+        .map(|(name, _, hash)| (name.as_str(), hash.as_str()))
+        .collect();
+    let local_by_hash: HashMap<&str, &str> = local
+        .iter()
+        .map(|(name, _, hash)| (hash.as_str(), name.as_str()))
+        .collect();
+    let server_by_hash: HashMap<&str, &str> = server_hashes
+        .iter()
+        .map(|(name, hash)| (hash.as_str(), name.as_str()))
+        .collect();
+
+    let mut entries: Vec<SyncEntry> = local
+        .iter()
+        .map(|(name, path, hash)| {
+            let status = match server_hashes.get(name) {
+                Some(server_hash) if server_hash == hash => SyncStatus::Same,
+                Some(_) => SyncStatus::Differs,
+                // Listed but not hashed yet, the name is all we can go by
+                None if library.contains(name) => SyncStatus::Same,
+                None => match server_by_hash.get(hash.as_str()) {
+                    Some(other) => SyncStatus::Renamed(other.to_string()),
+                    None => SyncStatus::LocalOnly,
+                },
+            };
+            SyncEntry {
+                name: name.clone(),
+                status,
+                path: Some(path.clone()),
+            }
+        })
+        .collect();
+
+    for name in library {
+        // Once hashes arrive, unhashed entries are directories, not songs
+        let not_a_song = !server_hashes.is_empty() && !server_hashes.contains_key(name);
+        if not_a_song || local_by_name.contains_key(name.as_str()) {
+            continue;
+        }
+        let status = server_hashes
+            .get(name)
+            .and_then(|hash| local_by_hash.get(hash.as_str()))
+            .map_or(SyncStatus::ServerOnly, |other| {
+                SyncStatus::Renamed(other.to_string())
+            });
+        entries.push(SyncEntry {
+            name: name.clone(),
+            status,
+            path: None,
+        });
+    }
+
+    entries.sort_by_key(|entry| entry.name.to_lowercase());
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local(songs: &[(&str, &str)]) -> Vec<LocalSong> {
+        songs
+            .iter()
+            .map(|(name, hash)| {
+                (
+                    name.to_string(),
+                    PathBuf::from("/songs").join(name),
+                    hash.to_string(),
+                )
+            })
+            .collect()
+    }
+
+    fn statuses(entries: &[SyncEntry]) -> Vec<(&str, SyncStatus)> {
+        entries
+            .iter()
+            .map(|entry| (entry.name.as_str(), entry.status.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn compares_by_name_then_by_hash() {
+        let local = local(&[
+            ("a.mp3", "1"),
+            ("B.mp3", "2"),
+            ("c.mp3", "4"),
+            ("d.mp3", "5"),
+        ]);
+        let hashes: HashMap<String, String> = [
+            ("a.mp3", "1"),
+            ("B.mp3", "3"),
+            ("c (live).mp3", "4"),
+            ("e.mp3", "6"),
+        ]
+        .iter()
+        .map(|(name, hash)| (name.to_string(), hash.to_string()))
+        .collect();
+        let mut library: Vec<String> = hashes.keys().cloned().collect();
+        library.push("albums".to_string());
+
+        let entries = diff(&local, &library, &hashes);
+        assert_eq!(
+            statuses(&entries),
+            vec![
+                ("a.mp3", SyncStatus::Same),
+                ("B.mp3", SyncStatus::Differs),
+                ("c (live).mp3", SyncStatus::Renamed("c.mp3".to_string())),
+                ("c.mp3", SyncStatus::Renamed("c (live).mp3".to_string())),
+                ("d.mp3", SyncStatus::LocalOnly),
+                ("e.mp3", SyncStatus::ServerOnly),
+            ]
+        );
+        assert_eq!(entries[0].path, Some(PathBuf::from("/songs/a.mp3")));
+        assert_eq!(entries[5].path, None);
+    }
+
+    #[test]
+    fn goes_by_name_until_hashes_arrive() {
+        let local = local(&[("a.mp3", "1"), ("b.mp3", "2")]);
+        let library = vec!["a.mp3".to_string(), "c.mp3".to_string()];
+
+        let entries = diff(&local, &library, &HashMap::new());
+        assert_eq!(
+            statuses(&entries),
+            vec![
+                ("a.mp3", SyncStatus::Same),
+                ("b.mp3", SyncStatus::LocalOnly),
+                ("c.mp3", SyncStatus::ServerOnly),
+            ]
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    widgets::BorderType,
+};
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Colors and border of the interface.
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    pub background: Color,
+    pub foreground: Color,
+    /// Titles, gauge and other things that should stand out
+    pub accent: Color,
+    /// Selected rows
+    pub highlight: Color,
+    pub border: Color,
+    pub error: Color,
+    pub warning: Color,
+    pub success: Color,
+    pub border_type: BorderType,
+}
+
+pub const THEMES: [&str; 5] = ["default", "high_contrast", "gruvbox", "solarized", "mono"];
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::bundled("default").unwrap()
+    }
+}
+
+impl Theme {
+    pub fn bundled(name: &str) -> Option<Self> {
+        let theme = |background,
+                     foreground,
+                     accent,
+                     highlight,
+                     border,
+                     error,
+                     warning,
+                     success,
+                     border_type| {
+            Self {
+                name: name.to_string(),
+                background,
+                foreground,
+                accent,
+                highlight,
+                border,
+                error,
+                warning,
+                success,
+                border_type,
+            }
+        };
+        Some(match name {
+            "default" => theme(
+                Color::Black,
+                Color::Cyan,
+                Color::LightCyan,
+                Color::White,
+                Color::Cyan,
+                Color::LightRed,
+                Color::Yellow,
+                Color::LightGreen,
+                BorderType::Rounded,
+            ),
+            "high_contrast" => theme(
+                Color::Black,
+                Color::White,
+                Color::Yellow,
+                Color::LightYellow,
+                Color::White,
+                Color::LightRed,
+                Color::LightMagenta,
+                Color::LightGreen,
+                BorderType::Thick,
+            ),
+            "gruvbox" => theme(
+                Color::Rgb(0x28, 0x28, 0x28),
+                Color::Rgb(0xeb, 0xdb, 0xb2),
+                Color::Rgb(0xfe, 0x80, 0x19),
+                Color::Rgb(0xfa, 0xbd, 0x2f),
+                Color::Rgb(0x92, 0x83, 0x74),
+                Color::Rgb(0xfb, 0x49, 0x34),
+                Color::Rgb(0xfe, 0x80, 0x19),
+                Color::Rgb(0xb8, 0xbb, 0x26),
+                BorderType::Rounded,
+            ),
+            "solarized" => theme(
+                Color::Rgb(0x00, 0x2b, 0x36),
+                Color::Rgb(0x83, 0x94, 0x96),
+                Color::Rgb(0x26, 0x8b, 0xd2),
+                Color::Rgb(0xee, 0xe8, 0xd5),
+                Color::Rgb(0x58, 0x6e, 0x75),
+                Color::Rgb(0xdc, 0x32, 0x2f),
+                Color::Rgb(0xb5, 0x89, 0x00),
+                Color::Rgb(0x85, 0x99, 0x00),
+                BorderType::Plain,
+            ),
+            // The terminal's own colors, emphasis only through modifiers
+            "mono" => theme(
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+                Color::Reset,
+                BorderType::Plain,
+            ),
+            _ => return None,
+        })
+    }
+
+    /// Base style of every panel.
+    pub fn style(&self) -> Style {
+        Style::new().fg(self.foreground).bg(self.background)
+    }
+
+    pub fn border_style(&self) -> Style {
+        Style::new().fg(self.border)
+    }
+
+    pub fn accent_style(&self) -> Style {
+        Style::new().fg(self.accent)
+    }
+
+    /// Border of the focused panel, bold to stand out without colors too.
+    pub fn focus_style(&self) -> Style {
+        Style::new().fg(self.accent).add_modifier(Modifier::BOLD)
+    }
+
+    /// Selected rows stay recognizable without colors.
+    pub fn highlight_style(&self) -> Style {
+        Style::new()
+            .fg(self.highlight)
+            .add_modifier(Modifier::ITALIC | Modifier::BOLD)
+    }
+
+    pub fn error_style(&self) -> Style {
+        Style::new().fg(self.error)
+    }
+
+    pub fn warning_style(&self) -> Style {
+        Style::new().fg(self.warning)
+    }
+
+    pub fn success_style(&self) -> Style {
+        Style::new().fg(self.success)
+    }
+
+    /// Reads the theme file, the default theme when there is none. `NO_COLOR`
+    /// (https://no-color.org) wins over both.
+    pub fn load(path: &str) -> Result<Self, String> {
+        if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            return Ok(Self::bundled("mono").unwrap());
+        }
+        let Ok(content) = std::fs::read_to_string(path) else {
+            return Ok(Self::default());
+        };
+        let config: ThemeConfig = serde_json::from_str(&content).map_err(|err| err.to_string())?;
+        let name = config.theme.as_deref().unwrap_or("default");
+        let mut theme = Self::bundled(name).ok_or_else(|| {
+            format!(
+                "unknown theme \"{}\", try one of {}",
+                name,
+                THEMES.join(", ")
+            )
+        })?;
+
+        for (color, value) in [
+            (&mut theme.background, &config.background),
+            (&mut theme.foreground, &config.foreground),
+            (&mut theme.accent, &config.accent),
+            (&mut theme.highlight, &config.highlight),
+            (&mut theme.border, &config.border),
+            (&mut theme.error, &config.error),
+            (&mut theme.warning, &config.warning),
+            (&mut theme.success, &config.success),
+        ] {
+            if let Some(value) = value {
+                *color =
+                    Color::from_str(value).map_err(|_| format!("unknown color \"{}\"", value))?;
+            }
+        }
+        if let Some(border_type) = config.border_type.as_deref() {
+            theme.border_type = match border_type {
+                "plain" => BorderType::Plain,
+                "rounded" => BorderType::Rounded,
+                "double" => BorderType::Double,
+                "thick" => BorderType::Thick,
+                _ => return Err(format!("unknown border type \"{}\"", border_type)),
+            };
+        }
+        Ok(theme)
+    }
+}
+
+/// The theme file: a bundled theme and colors replacing some of its own.
+///
+/// `{"theme": "gruvbox", "accent": "#83a598", "border_type": "double"}`
+#[derive(Debug, Default, Deserialize)]
+struct ThemeConfig {
+    theme: Option<String>,
+    background: Option<String>,
+    foreground: Option<String>,
+    accent: Option<String>,
+    highlight: Option<String>,
+    border: Option<String>,
+    error: Option<String>,
+    warning: Option<String>,
+    success: Option<String>,
+    border_type: Option<String>,
+}
@@ -0,0 +1,67 @@
+use crate::event::Event;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tokio::sync::mpsc;
+
+/// Watches the directory shown in the local explorer (inotify on Linux)
+/// and tells the event loop when its contents change.
+pub struct LibraryWatcher {
+    watcher: RecommendedWatcher,
+    watched: Option<PathBuf>,
+    /// Set while a change is waiting to be picked up, so a burst of
+    /// filesystem events results in a single refresh
+    pending: Arc<AtomicBool>,
+}
+
+impl std::fmt::Debug for LibraryWatcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LibraryWatcher")
+            .field("watched", &self.watched)
+            .finish()
+    }
+}
+
+impl LibraryWatcher {
+    pub fn new(sender: mpsc::UnboundedSender<Event>) -> notify::Result<Self> {
+        let pending = Arc::new(AtomicBool::new(false));
+        let watcher = notify::recommended_watcher({
+            let pending = pending.clone();
+            move |result: notify::Result<notify::Event>| {
+                let Ok(event) = result else {
+                    return;
+                };
+                if event.kind.is_access() || pending.swap(true, Ordering::Relaxed) {
+                    return;
+                }
+                let _ = sender.send(Event::LocalLibraryChanged);
+            }
+        })?;
+        Ok(Self {
+            watcher,
+            watched: None,
+            pending,
+        })
+    }
+
+    /// Moves the watch to `dir`, dropping the previous one.
+    pub fn watch(&mut self, dir: &Path) -> notify::Result<()> {
+        if self.watched.as_deref() == Some(dir) {
+            return Ok(());
+        }
+        if let Some(previous) = self.watched.take() {
+            let _ = self.watcher.unwatch(&previous);
+        }
+        self.watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        self.watched = Some(dir.to_path_buf());
+        Ok(())
+    }
+
+    /// Called once the change has been handled.
+    pub fn acknowledge(&self) {
+        self.pending.store(false, Ordering::Relaxed);
+    }
+}
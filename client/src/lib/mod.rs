@@ -1,5 +1,17 @@
 #![allow(non_snake_case)]
+pub mod AudioFile;
+pub mod Codec;
+pub mod Download;
 pub mod FileExplorer;
+pub mod FuzzySearch;
+pub mod History;
+pub mod Keymap;
+pub mod Logging;
 pub mod NetUtils;
 pub mod Playback;
+pub mod Playlist;
+pub mod Ratings;
 pub mod RawAudioSource;
+pub mod Sync;
+pub mod Theme;
+pub mod Watcher;
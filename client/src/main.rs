@@ -1,22 +1,26 @@
+// `lib` is a plain module directory here, not a library target
+#![allow(special_module_name)]
+
 use std::env;
 use std::io;
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
-use std::usize;
+use std::sync::{atomic::Ordering, Arc};
 
-use handler::{handle_file_actions, handle_network_communication};
+use handler::{handle_batch, handle_mouse_events, handle_network_communication};
 use ratatui::{backend::CrosstermBackend, Terminal};
 use tokio::io::{AsyncReadExt, BufReader};
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, Mutex, Notify};
+use tracing::{debug, info, trace};
 
 use crate::{
-    app::{App, AppResult},
+    app::{App, AppResult, Severity},
     event::{Event, EventHandler},
     handler::handle_key_events,
-    lib::Playback,
+    lib::{
+        Codec::{AudioPacket, PacketReader},
+        NetUtils::{sendHandshake, sendNickname},
+        Playback,
+    },
     tui::Tui,
 };
 
@@ -40,21 +44,40 @@ async fn main() -> AppResult<()> {
 
     let server_comm_connection_string: String = format!("{}:{}", &args[1], &args[2]);
     let server_audio_connection_string: String = format!("{}:{}", &args[1], &args[3]);
+    // Optional opus bitrate in kbps
+    if let Some(bitrate) = args.get(4).and_then(|kbps| kbps.parse::<u32>().ok()) {
+        app.bitrate = bitrate * 1000;
+    }
+    // Optional nickname, defaults to the login name
+    if let Some(nickname) = args.get(5) {
+        app.nickname = nickname.clone();
+    }
+    // Favorites and ratings are kept per server
+    app.set_server(&server_comm_connection_string);
 
     // Initialize the terminal user interface.
     let backend = CrosstermBackend::new(io::stdout());
     let terminal = Terminal::new(backend)?;
-    let c_stream: TcpStream = TcpStream::connect(server_comm_connection_string).await?;
+    info!(server = %server_comm_connection_string, "connecting");
+    let c_stream: TcpStream = TcpStream::connect(&server_comm_connection_string).await?;
+    info!(audio = %server_audio_connection_string, "connected, opening the audio stream");
     let a_stream: TcpStream = TcpStream::connect(server_audio_connection_string).await?;
-    app.add_comm_connection(c_stream);
+    let (c_reader, c_writer) = c_stream.into_split();
+    app.add_comm_connection(c_writer);
     // app.add_audio_connection(a_stream);
-    let connection = app.c_connection.clone().unwrap();
+    // Until the server answers the handshake the stream stays raw
+    sendHandshake(&mut app).await?;
+    sendNickname(&mut app).await?;
+    debug!(nickname = %app.nickname, bitrate = app.bitrate, "handshake sent");
 
-    let events = EventHandler::new(250, connection.clone());
+    let events = EventHandler::new(250, c_reader);
+    app.watch_local_library(events.sender());
     let mut tui = Tui::new(terminal, events);
     tui.init()?;
+    let (width, height) = crossterm::terminal::size()?;
+    app.resize(width, height);
 
-    let (tx, rx): (mpsc::Sender<Vec<u8>>, mpsc::Receiver<Vec<u8>>) = mpsc::channel(32);
+    let (tx, rx): (mpsc::Sender<AudioPacket>, mpsc::Receiver<AudioPacket>) = mpsc::channel(32);
     let rx = Arc::new(Mutex::new(rx));
 
     let (_stream, stream_handle) = OutputStream::try_default().unwrap();
@@ -71,30 +94,55 @@ async fn main() -> AppResult<()> {
     let shutdown_notify = Arc::new(Notify::new());
 
     let shutdown_signal = shutdown_notify.clone();
+    // The audio reader reports problems through the event queue
+    let notifications = tui.events.sender();
     tokio::spawn(async move {
         let tx = tx.clone(); // Clone the channel sender.
         let mut buffer = vec![0; CHUNK_SIZE];
         let mut reader = BufReader::new(a_stream);
-        loop {
+        let mut packets = PacketReader::new();
+        info!("audio reader started");
+        'stream: loop {
             // Perform the actual read from the stream
             tokio::select! {
             result = reader.read(&mut buffer) => {
             match result {
                 Ok(0) => {
                     // Stream closed
-                    eprintln!("Stream closed");
+                    let _ = notifications.send(Event::Notify(
+                        Severity::Warning,
+                        "The audio stream closed".to_string(),
+                    ));
                     break;
                 }
                 Ok(size) => {
-                    // Truncate to the actual read size before sending
-                    buffer.truncate(size);
-                    if let Err(e) = tx.send(buffer.clone()).await {
-                        eprintln!("Failed to send audio to playback: {}", e);
-                        break;
+                    trace!(size, "audio read");
+                    // Only the actual read size is sent to playback
+                    let audio = match packets.push(&buffer[..size]) {
+                        Ok(audio) => audio,
+                        Err(e) => {
+                            let _ = notifications.send(Event::Notify(
+                                Severity::Error,
+                                format!("Can't read the audio stream: {}", e),
+                            ));
+                            break;
+                        }
+                    };
+                    for packet in audio {
+                        if let Err(e) = tx.send(packet).await {
+                            let _ = notifications.send(Event::Notify(
+                                Severity::Error,
+                                format!("Can't play the audio: {}", e),
+                            ));
+                            break 'stream;
+                        }
                     }
                 }
                 Err(e) => {
-                    eprintln!("Failed to read from stream: {}", e);
+                    let _ = notifications.send(Event::Notify(
+                        Severity::Error,
+                        format!("Can't read the audio stream: {}", e),
+                    ));
                     break;
                 }
             }}
@@ -104,6 +152,7 @@ async fn main() -> AppResult<()> {
                 }
             }
         }
+        info!("audio reader stopped");
     });
 
     // Start the TUI loop.
@@ -112,14 +161,21 @@ async fn main() -> AppResult<()> {
         tui.draw(&mut app)?;
         // Handle events.
         match tui.events.next().await? {
-            Event::Tick => app.tick(),
-            Event::Key(key_event) => handle_key_events(key_event, &mut app)?,
-            Event::Mouse(_) => {}
-            Event::Resize(_, _) => {}
+            Event::Tick => {
+                app.tick();
+                handle_batch(&mut app).await;
+            }
+            Event::Key(key_event) => handle_key_events(key_event, &mut app).await?,
+            Event::Mouse(mouse_event) => handle_mouse_events(mouse_event, &mut app).await?,
+            Event::Resize(width, height) => app.resize(width, height),
             Event::Net(message) => handle_network_communication(&message, &mut app)?,
-            Event::FileTransfer => handle_file_actions(&mut app).await?,
+            Event::LocalLibraryChanged => app.refresh_client_entries(),
+            Event::Notify(severity, text) => app.notify(severity, text),
+            Event::Probed(probes) => app.add_probes(probes),
+            Event::SyncScanned(hashes, songs) => app.add_sync_scan(hashes, songs),
         }
     }
+    info!("shutting down");
     shutdown_notify.notify_waiters();
     // Exit the user interface.
     tui.exit()?;
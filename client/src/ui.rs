@@ -1,126 +1,1036 @@
 use ratatui::{
-    layout::{Alignment, Constraint, Direction, Layout},
-    style::{Color, Style, Stylize},
-    widgets::{Block, BorderType, Gauge, List, ListDirection, Paragraph},
+    layout::{Alignment, Constraint, Direction, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Gauge, List, ListDirection, Paragraph, Wrap},
     Frame,
 };
 
-use crate::app::App;
-use crate::lib::FileExplorer;
+use crate::app::{App, Areas, Panel, Severity, BURST_TICKS, TOAST_LIMIT};
+use crate::lib::{
+    AudioFile::format_duration,
+    Keymap::{describe_sequence, Action, Keymap, ACTIONS},
+    NetUtils::REACTIONS,
+    Sync::SyncStatus,
+    Theme::Theme,
+};
+use chrono::{Local, TimeZone};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::Level;
 
 // Custom widgets
 
-/// Renders the user interface widgets.
-pub fn render(app: &mut App, frame: &mut Frame) {
-    // This is where you add new widgets.
-    // See the following resources:
-    // - https://docs.rs/ratatui/latest/ratatui/widgets/index.html
-    // - https://github.com/ratatui/ratatui/tree/master/examples
+/// Pads or truncates a cell to a fixed width column.
+fn column(cell: &str, width: usize) -> String {
+    let cell: String = cell.chars().take(width.saturating_sub(1)).collect();
+    format!("{:<width$}", cell, width = width)
+}
+
+/// Lays out cells as fixed width columns, truncating what doesn't fit.
+fn columns(cells: &[String], widths: &[usize]) -> String {
+    cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, &width)| column(cell, width))
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+/// Emphasizes the characters a search query matched.
+fn highlight(text: &str, indices: &[usize]) -> Vec<Span<'static>> {
+    text.chars()
+        .enumerate()
+        .map(|(position, character)| {
+            let span = Span::raw(character.to_string());
+            if indices.contains(&position) {
+                span.style(Style::new().bold().underlined())
+            } else {
+                span
+            }
+        })
+        .collect()
+}
+
+/// Bottom line of a filtered explorer.
+fn search_title(app: &App, client: bool) -> Line<'static> {
+    match app.search.as_ref() {
+        Some(search) if search.client == client => Line::from(format!(
+            "/{}{}",
+            search.query,
+            if search.editing { "_" } else { "" }
+        ))
+        .left_aligned(),
+        _ => Line::default(),
+    }
+}
+
+/// How long ago a unix timestamp was, e.g. "5m" or "2h07m".
+fn connected_for(since: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(since, |now| now.as_secs());
+    let minutes = now.saturating_sub(since) / 60;
+    if minutes < 60 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}h{:02}m", minutes / 60, minutes % 60)
+    }
+}
+
+/// First key bound to an action, as the user has to type it.
+fn key(keymap: &Keymap, action: Action) -> String {
+    keymap
+        .keys(action)
+        .into_iter()
+        .next()
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// "[key] what it does", following the keymap.
+fn hint(keymap: &Keymap, action: Action, label: &str) -> String {
+    format!("[{}] {}", key(keymap, action), label)
+}
+
+/// "[up/down] scroll" for the scrolling overlays.
+fn scroll_hint(keymap: &Keymap) -> String {
+    format!(
+        "[{}/{}] scroll",
+        key(keymap, Action::Up),
+        key(keymap, Action::Down)
+    )
+}
+
+/// Progress of running downloads, or the key that starts one.
+fn download_title(app: &App) -> String {
+    match app.downloads.len() {
+        0 => hint(&app.keymap, Action::Download, "download"),
+        1 => app
+            .downloads
+            .values()
+            .map(|download| format!("↓ {} {}%", download.name, download.progress()))
+            .collect(),
+        // Batches show one figure for all of their songs
+        count => {
+            let received: u64 = app
+                .downloads
+                .values()
+                .map(|download| download.received)
+                .sum();
+            let size: u64 = app.downloads.values().map(|download| download.size).sum();
+            let progress = (received * 100).checked_div(size).unwrap_or(100);
+            format!("↓ {} songs {}%", count, progress)
+        }
+    }
+}
+
+/// Mark count in the top right corner of an explorer.
+fn marks_title(keymap: &Keymap, count: usize) -> Line<'static> {
+    match count {
+        0 => Line::from(hint(keymap, Action::Mark, "mark")).right_aligned(),
+        count => Line::from(format!(
+            "{} marked {} {}",
+            count,
+            hint(keymap, Action::MarkAll, "all"),
+            hint(keymap, Action::ClearMarks, "none")
+        ))
+        .right_aligned(),
+    }
+}
+
+/// " ♥ ★★★" after a favorite or rated song.
+fn rating(app: &App, song: &str) -> String {
+    let mut rating = String::new();
+    if app.ratings.is_favorite(song) {
+        rating.push_str(" ♥");
+    }
+    match app.ratings.stars(song) {
+        0 => rating,
+        stars => format!("{} {}", rating, "★".repeat(stars as usize)),
+    }
+}
+
+/// "● " in front of marked rows.
+fn mark(marked: bool) -> Span<'static> {
+    if marked {
+        Span::raw("● ").bold()
+    } else {
+        Span::raw("  ")
+    }
+}
+
+/// Bordered block in the colors and border type of the theme.
+fn block(theme: &Theme) -> Block<'static> {
+    Block::bordered()
+        .border_type(theme.border_type)
+        .border_style(theme.border_style())
+        .title_style(theme.accent_style())
+}
+
+/// Block of a panel that can take the focus, its border stands out while it has it.
+fn panel_block(theme: &Theme, focused: bool) -> Block<'static> {
+    let block = block(theme);
+    if focused {
+        block.border_style(theme.focus_style())
+    } else {
+        block
+    }
+}
+
+/// Selected row style, only the focused panel shows it in full.
+fn row_highlight(theme: &Theme, focused: bool) -> Style {
+    if focused {
+        theme.highlight_style()
+    } else {
+        Style::new().add_modifier(Modifier::DIM)
+    }
+}
+
+/// "Local files (12)", or "Local files (3 of 12)" while filtered.
+fn count_title(name: &str, shown: usize, total: usize) -> String {
+    if shown == total {
+        format!("{} ({})", name, total)
+    } else {
+        format!("{} ({} of {})", name, shown, total)
+    }
+}
+
+/// Title and style of a notification.
+fn severity(theme: &Theme, severity: Severity) -> (&'static str, Style) {
+    match severity {
+        Severity::Info => ("Info", theme.accent_style()),
+        Severity::Warning => ("Warning", theme.warning_style()),
+        Severity::Error => ("Error", theme.error_style()),
+    }
+}
+
+/// Area of the given size in the middle of `area`.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+/// Where every widget goes: the layout in assets/tui_desing.jpeg, one panel
+/// at a time with tabs on small terminals, or the zoomed panel alone.
+fn layout(app: &App, area: Rect) -> Areas {
+    let mut areas = Areas::default();
+    let screen_layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(vec![Constraint::Fill(1), Constraint::Length(1)])
+        .split(area);
+    areas.status = screen_layout[1];
 
-    let default_style = Style::default().fg(Color::Cyan).bg(Color::Black);
+    if app.zoomed {
+        panel_layout(app.focus, screen_layout[0], &mut areas);
+        return areas;
+    }
+    if app.compact {
+        let compact_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(vec![
+                Constraint::Length(1),
+                Constraint::Fill(1),
+                Constraint::Length(3),
+            ])
+            .split(screen_layout[0]);
+        let tabs_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(
+                Panel::ALL.map(|panel| Constraint::Length(tab_label(panel).chars().count() as u16)),
+            )
+            .split(compact_layout[0]);
+        areas.tabs.copy_from_slice(&tabs_layout);
+        panel_layout(app.focus, compact_layout[1], &mut areas);
+        areas.gauge = compact_layout[2];
+        return areas;
+    }
 
-    // Creating the layout in assets/tui_desing.jpeg
     let main_layout = Layout::default()
         .direction(Direction::Horizontal)
         .constraints(vec![Constraint::Percentage(75), Constraint::Percentage(25)])
-        .split(frame.area());
+        .split(screen_layout[0]);
     let functional_layout = Layout::default()
         .direction(Direction::Vertical)
-        .constraints(vec![Constraint::Fill(1), Constraint::Length(5)])
+        .constraints(vec![
+            Constraint::Fill(1),
+            Constraint::Length(12),
+            Constraint::Length(5),
+        ])
         .split(main_layout[0]);
-    let queue_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints(vec![Constraint::Length(5), Constraint::Fill(1)]) // Cons
-        .split(main_layout[1]);
     let fs_layout = Layout::default()
         .direction(Direction::Horizontal)
         .constraints(vec![Constraint::Percentage(50), Constraint::Percentage(50)])
         .split(functional_layout[0]);
-    let server_layout = Layout::default()
-        .direction(Direction::Vertical)
-        .constraints(vec![Constraint::Length(5), Constraint::Fill(1)])
-        .split(fs_layout[0]);
+    panel_layout(Panel::Server, fs_layout[0], &mut areas);
+    panel_layout(Panel::Client, fs_layout[1], &mut areas);
+    panel_layout(Panel::Chat, functional_layout[1], &mut areas);
+    panel_layout(Panel::Queue, main_layout[1], &mut areas);
+    areas.gauge = functional_layout[2];
+    areas.views = functional_layout[0];
+    areas
+}
+
+/// Splits the area of a panel among its widgets.
+fn panel_layout(panel: Panel, area: Rect, areas: &mut Areas) {
+    areas.views = area;
+    match panel {
+        Panel::Server => {
+            let server_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![Constraint::Length(5), Constraint::Fill(1)])
+                .split(area);
+            areas.server_info = server_layout[0];
+            areas.server = server_layout[1];
+        }
+        Panel::Client => areas.client = area,
+        Panel::Queue => {
+            // The roster gives way to the queue on short screens
+            let queue_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![
+                    Constraint::Length(5),
+                    Constraint::Fill(1),
+                    Constraint::Length((area.height / 4).min(8)),
+                ])
+                .split(area);
+            areas.queue_info = queue_layout[0];
+            areas.queue = queue_layout[1];
+            areas.listeners = queue_layout[2];
+        }
+        Panel::Chat => {
+            let chat_layout = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints(vec![Constraint::Fill(1), Constraint::Length(3)])
+                .split(area);
+            areas.chat = chat_layout[0];
+            areas.chat_input = chat_layout[1];
+        }
+    }
+}
+
+fn tab_label(panel: Panel) -> &'static str {
+    match panel {
+        Panel::Server => " Server ",
+        Panel::Client => " Local ",
+        Panel::Queue => " Queue ",
+        Panel::Chat => " Chat ",
+    }
+}
+
+/// Renders the user interface widgets.
+pub fn render(app: &mut App, frame: &mut Frame) {
+    // This is where you add new widgets.
+    // See the following resources:
+    // - https://docs.rs/ratatui/latest/ratatui/widgets/index.html
+    // - https://github.com/ratatui/ratatui/tree/master/examples
+
+    let theme = app.theme.clone();
+    let default_style = theme.style();
+
+    let areas = layout(app, frame.area());
+    app.areas = areas;
 
     // Server File Explorer
+    let batch = app.upload_batch.as_ref().map_or(String::new(), |batch| {
+        let failed = match batch.failed.len() {
+            0 => String::new(),
+            failed => format!(", {} failed", failed),
+        };
+        format!("↑ Uploading {}/{}{}", batch.done(), batch.total, failed)
+    });
     frame.render_widget(
         Paragraph::new(format!(
-            "Active listeners: {}",
-            app.state.active_listeners.to_string()
+            "Active listeners: {}\nListening as: {}\n{}",
+            app.state.active_listeners, app.nickname, batch
         ))
-        .block(Block::bordered().border_type(BorderType::Rounded))
+        .block(block(&theme))
         .style(default_style),
-        server_layout[0],
+        areas.server_info,
     );
 
-    // ToDo: Get songs from server via async Tokio receiver
-    let server_items = app.state.song_library.clone();
+    // Songs uploaded with tags are shown as "artist - title (length)"
+    let server_items: Vec<Line> = app
+        .view(false)
+        .iter()
+        .map(|row| {
+            let song = &app.state.song_library[row.index];
+            let label = app.server_label(song);
+            let mut spans = vec![mark(app.server_marks.contains(song))];
+            spans.extend(highlight(&label, &row.indices));
+            spans.push(Span::raw(rating(app, song)));
+            Line::from(spans)
+        })
+        .collect();
 
+    let server_title = count_title(
+        "Server library",
+        server_items.len(),
+        app.state.song_library.len(),
+    );
     let server_list = List::new(server_items);
+    let favorites_title = if app.favorites_only {
+        format!("♥ only [{}]", key(&app.keymap, Action::FavoritesOnly))
+    } else {
+        format!(
+            "[{}]♥ [{}]★ [{}]filter",
+            key(&app.keymap, Action::Favorite),
+            key(&app.keymap, Action::Rate),
+            key(&app.keymap, Action::FavoritesOnly)
+        )
+    };
 
     frame.render_stateful_widget(
         server_list
             .block(
-                Block::bordered()
-                    .title("Songs to send")
-                    .border_type(BorderType::Rounded)
+                panel_block(&theme, app.focus == Panel::Server)
+                    .title(Line::from(favorites_title).left_aligned())
+                    .title(server_title)
+                    .title(marks_title(&app.keymap, app.server_marks.len()))
+                    .title_bottom(search_title(app, false))
+                    .title_bottom(Line::from(download_title(app)).right_aligned())
                     .title_alignment(Alignment::Center),
             )
             .style(default_style)
-            .highlight_style(Style::new().italic())
+            .highlight_style(row_highlight(&theme, app.focus == Panel::Server))
             .highlight_symbol(">> ")
             .repeat_highlight_symbol(true)
             .direction(ListDirection::TopToBottom),
-        server_layout[1],
+        areas.server,
         &mut app.server_fs_state,
     );
 
-    let client_items = FileExplorer::get_dir_contents(app.song_dir);
+    // Title | Artist | Album | Length | Format, minus borders, highlight symbol and mark
+    let width = (areas.client.width as usize).saturating_sub(7);
+    let widths = [width * 30 / 100, width * 22 / 100, width * 22 / 100, 7, width];
+    let client_items: Vec<Line> = app
+        .view(true)
+        .iter()
+        .map(|row| {
+            let entry = app.client_entries[row.index].clone();
+            let label = app.client_label(&entry);
+            let mut spans = vec![mark(app.client_marks.contains(&entry.path))];
+            if entry.is_dir {
+                spans.extend(highlight(&label, &row.indices));
+                return Line::from(spans);
+            }
+            let probe = app.probe_entry(&entry);
+            let rest = columns(
+                &[
+                    probe.artist.clone().unwrap_or_default(),
+                    probe.album.clone().unwrap_or_default(),
+                    probe.duration.map(format_duration).unwrap_or_default(),
+                    probe.describe(),
+                ],
+                &widths[1..],
+            );
+            spans.extend(highlight(&column(&label, widths[0]), &row.indices));
+            spans.push(Span::raw(rest));
+            Line::from(spans)
+        })
+        .collect();
 
+    let client_title = count_title(
+        "Local files",
+        client_items.len(),
+        app.client_entries.len(),
+    );
     let client_list = List::new(client_items);
 
     // Client File Explorer
+    let keymap = &app.keymap;
+    let file_hints = format!(
+        "{} | {} | {} | {}",
+        hint(
+            keymap,
+            Action::ToggleTranscode,
+            if app.transcode_uploads {
+                "transcode: on"
+            } else {
+                "transcode: off"
+            }
+        ),
+        hint(
+            keymap,
+            Action::CycleSort,
+            &format!("sort: {}", app.explorer.sort.name())
+        ),
+        hint(
+            keymap,
+            Action::ToggleAudioOnly,
+            if app.explorer.audio_only {
+                "audio only"
+            } else {
+                "all files"
+            }
+        ),
+        hint(
+            keymap,
+            Action::ToggleHidden,
+            if app.explorer.show_hidden {
+                "hidden: shown"
+            } else {
+                "hidden: hidden"
+            }
+        ),
+    );
     frame.render_stateful_widget(
         client_list
             .block(
-                Block::bordered()
-                    .title("Songs to send")
-                    .border_type(BorderType::Rounded)
+                panel_block(&theme, app.focus == Panel::Client)
+                    .title(client_title)
+                    .title(Line::from(app.explorer.breadcrumbs()).left_aligned())
+                    .title(marks_title(&app.keymap, app.client_marks.len()))
+                    .title_bottom(search_title(app, true))
+                    .title_bottom(file_hints)
                     .title_alignment(Alignment::Center),
             )
             .style(default_style)
-            .highlight_style(Style::new().italic())
+            .highlight_style(row_highlight(&theme, app.focus == Panel::Client))
             .highlight_symbol(">> ")
             .repeat_highlight_symbol(true)
             .direction(ListDirection::TopToBottom),
-        fs_layout[1],
+        areas.client,
         &mut app.client_fs_state,
     );
 
+    // The sync view replaces both explorers while it is open
+    if let Some(sync) = app.sync.as_mut() {
+        frame.render_widget(Clear, areas.views);
+        let rows: Vec<Line> = sync
+            .entries
+            .iter()
+            .map(|entry| {
+                let line = Line::from(format!("{:<24}{}", entry.status.describe(), entry.name));
+                match entry.status {
+                    SyncStatus::Same => line.style(theme.success_style()),
+                    SyncStatus::Differs => line.style(theme.error_style()),
+                    _ => line,
+                }
+            })
+            .collect();
+        let local_only = sync
+            .entries
+            .iter()
+            .filter(|entry| entry.status == SyncStatus::LocalOnly)
+            .count();
+        let server_only = sync
+            .entries
+            .iter()
+            .filter(|entry| entry.status == SyncStatus::ServerOnly)
+            .count();
+        frame.render_stateful_widget(
+            List::new(rows)
+                .block(
+                    block(&theme)
+                        .title(if sync.scanning {
+                            format!("Sync {} ↔ server: hashing local songs…", app.song_dir)
+                        } else {
+                            format!(
+                                "Sync {} ↔ server: {} to upload, {} to download",
+                                app.song_dir, local_only, server_only
+                            )
+                        })
+                        .title_bottom(format!(
+                            "{} | {} | {} | {}",
+                            hint(&app.keymap, Action::UploadAll, "upload all"),
+                            hint(&app.keymap, Action::Download, "download all"),
+                            hint(&app.keymap, Action::Activate, "sync one"),
+                            hint(&app.keymap, Action::ToggleSync, "close")
+                        ))
+                        .title_alignment(Alignment::Center),
+                )
+                .style(default_style)
+                .highlight_style(theme.highlight_style())
+                .highlight_symbol(">> ")
+                .direction(ListDirection::TopToBottom),
+            areas.views,
+            &mut sync.state,
+        );
+    }
+
+    // History view, same place as the sync view
+    if let Some(view) = app.history_view.as_ref() {
+        let labels = app.history_labels();
+        let rows: Vec<Line> = view
+            .matches
+            .iter()
+            .map(|row| {
+                let entry = &app.history[app.history.len() - 1 - row.index];
+                let mut spans = highlight(&labels[row.index], &row.indices);
+                let reactions: Vec<String> = REACTIONS
+                    .iter()
+                    .zip(&entry.reactions)
+                    .filter(|(_, count)| **count > 0)
+                    .map(|(emoji, count)| format!("{} {}", emoji, count))
+                    .collect();
+                spans.push(Span::raw(format!("  {}", reactions.join(" "))));
+                Line::from(spans)
+            })
+            .collect();
+        let query = if view.query.is_empty() && !view.editing {
+            hint(&app.keymap, Action::Search, "search")
+        } else {
+            format!("/{}{}", view.query, if view.editing { "_" } else { "" })
+        };
+        let list = List::new(rows)
+            .block(
+                block(&theme)
+                    .title(format!("History: {} songs", app.history.len()))
+                    .title_bottom(Line::from(query).left_aligned())
+                    .title_bottom(
+                        Line::from(format!(
+                            "{} | {} | {}",
+                            hint(&app.keymap, Action::Requeue, "enqueue again"),
+                            hint(&app.keymap, Action::ExportQueue, "export"),
+                            hint(&app.keymap, Action::ToggleHistory, "close")
+                        ))
+                        .right_aligned(),
+                    )
+                    .title_alignment(Alignment::Center),
+            )
+            .style(default_style)
+            .highlight_style(theme.highlight_style())
+            .highlight_symbol(">> ")
+            .direction(ListDirection::TopToBottom);
+        frame.render_widget(Clear, areas.views);
+        if let Some(view) = app.history_view.as_mut() {
+            frame.render_stateful_widget(list, areas.views, &mut view.state);
+        }
+    }
+
+    // Log viewer, following the newest entries unless scrolled back
+    if let Some(scroll) = app.log_scroll {
+        let entries = app.log.entries();
+        let area = areas.views;
+        let visible = area.height.saturating_sub(2) as usize;
+        let scroll = scroll.min(entries.len().saturating_sub(visible));
+        app.log_scroll = Some(scroll);
+        let end = entries.len() - scroll;
+        let lines: Vec<Line> = entries[end.saturating_sub(visible)..end]
+            .iter()
+            .map(|entry| {
+                let style = match entry.level {
+                    Level::ERROR => theme.error_style(),
+                    Level::WARN => theme.warning_style(),
+                    _ => default_style,
+                };
+                Line::from(vec![
+                    Span::raw(format!("{} ", entry.time)),
+                    Span::styled(format!("{:<5} ", entry.level), style.bold()),
+                    Span::styled(format!("{}: ", entry.target), theme.accent_style()),
+                    Span::raw(entry.message.as_str()),
+                ])
+            })
+            .collect();
+        let position = if scroll == 0 {
+            "following".to_string()
+        } else {
+            format!("{} back", scroll)
+        };
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(
+                    block(&theme)
+                        .title(format!("Log: {}", app.log_dir()))
+                        .title_bottom(Line::from(position).left_aligned())
+                        .title_bottom(
+                            Line::from(format!(
+                                "{} {}",
+                                scroll_hint(&app.keymap),
+                                hint(&app.keymap, Action::ToggleLog, "close")
+                            ))
+                            .right_aligned(),
+                        )
+                        .title_alignment(Alignment::Center),
+                )
+                .style(default_style),
+            area,
+        );
+    }
+
     // Queue
     frame.render_widget(
         Paragraph::new("JamQueue")
-            .block(Block::bordered().border_type(BorderType::Rounded))
+            .block(
+                block(&theme).title_bottom(
+                    Line::from(format!(
+                        "{} {} {}",
+                        hint(&app.keymap, Action::Help, "help"),
+                        hint(&app.keymap, Action::ExportQueue, "export"),
+                        hint(&app.keymap, Action::ToggleHistory, "history")
+                    ))
+                    .right_aligned(),
+                ),
+            )
             .style(default_style),
-        queue_layout[0],
+        areas.queue_info,
     );
-    let song_queue = app.state.song_queue.clone();
-    frame.render_widget(
+    // The first entry is the song on air, it shows skip votes instead of a score
+    let song_queue: Vec<String> = app
+        .state
+        .song_queue
+        .iter()
+        .enumerate()
+        .map(|(position, song)| {
+            let label = app.server_label(song);
+            let queued_by = match app.state.queued_by.get(position) {
+                Some(nickname) => format!("  by {}", nickname),
+                None => String::new(),
+            };
+            if position == 0 {
+                return format!(
+                    "▶ {}  skip {}/{}{}",
+                    label, app.state.skip_votes, app.state.skip_needed, queued_by
+                );
+            }
+            let votes = app
+                .state
+                .queue_votes
+                .get(position)
+                .copied()
+                .unwrap_or_default();
+            format!("  {}  ▲{} ▼{}{}", label, votes.up, votes.down, queued_by)
+        })
+        .collect();
+    frame.render_stateful_widget(
         List::new(song_queue)
-            .block(Block::bordered().border_type(BorderType::Rounded))
+            .block(
+                panel_block(&theme, app.focus == Panel::Queue)
+                    .title(format!("Queue ({})", app.state.song_queue.len()))
+                    .title_bottom(format!(
+                        "{} {} {} [{}/{}] vote {}",
+                        hint(&app.keymap, Action::Remove, "remove"),
+                        hint(&app.keymap, Action::PlayNext, "next"),
+                        hint(&app.keymap, Action::ClearQueue, "clear"),
+                        key(&app.keymap, Action::Upvote),
+                        key(&app.keymap, Action::Downvote),
+                        hint(&app.keymap, Action::VoteSkip, "vote skip")
+                    ))
+                    .title_alignment(Alignment::Center),
+            )
+            .style(default_style)
+            .highlight_style(row_highlight(&theme, app.focus == Panel::Queue))
+            .highlight_symbol(">> ")
+            .direction(ListDirection::TopToBottom),
+        areas.queue,
+        &mut app.queue_state,
+    );
+
+    // Listener roster with what each of them has in the queue
+    let roster: Vec<String> = app
+        .state
+        .listeners
+        .iter()
+        .map(|listener| {
+            let queued: Vec<String> = app
+                .state
+                .song_queue
+                .iter()
+                .zip(&app.state.queued_by)
+                .filter(|(_, nickname)| **nickname == listener.nickname)
+                .map(|(song, _)| app.server_label(song))
+                .collect();
+            format!(
+                "{} ({}) {}",
+                listener.nickname,
+                connected_for(listener.connected_since),
+                queued.join(", ")
+            )
+        })
+        .collect();
+    frame.render_widget(
+        List::new(roster)
+            .block(
+                block(&theme)
+                    .title(format!("Listeners ({})", app.state.listeners.len()))
+                    .title_alignment(Alignment::Center),
+            )
             .style(default_style),
-        queue_layout[1],
+        areas.listeners,
     );
 
-    // Audio progress bar
+    // Chat, newest line at the bottom unless scrolled back
+    let visible = areas.chat.height.saturating_sub(2) as usize;
+    let end = app.chat.len().saturating_sub(app.chat_scroll);
+    let chat: Vec<Line> = app
+        .chat
+        .range(end.saturating_sub(visible)..end)
+        .map(|message| {
+            let time = Local
+                .timestamp_opt(message.time, 0)
+                .single()
+                .map_or(String::new(), |time| time.format("%H:%M").to_string());
+            if message.system {
+                Line::from(format!("[{}] * {}", time, message.text)).italic()
+            } else {
+                Line::from(format!("[{}] {}: {}", time, message.from, message.text))
+            }
+        })
+        .collect();
+    let scrolled = if app.chat_scroll > 0 {
+        format!("{} newer", app.chat_scroll)
+    } else {
+        String::new()
+    };
+    frame.render_widget(
+        Paragraph::new(chat)
+            .block(
+                panel_block(&theme, app.focus == Panel::Chat)
+                    .title("Chat")
+                    .title_bottom(Line::from(scrolled).right_aligned())
+                    .title_alignment(Alignment::Center),
+            )
+            .style(default_style),
+        areas.chat,
+    );
+    let chat_focused = app.focus == Panel::Chat;
+    frame.render_widget(
+        Paragraph::new(format!(
+            "{}{}",
+            app.chat_input,
+            if chat_focused { "_" } else { "" }
+        ))
+        .block(panel_block(&theme, chat_focused).title(if chat_focused {
+            "Message [enter] send [esc] leave".to_string()
+        } else {
+            hint(&app.keymap, Action::FocusChat, "chat")
+        }))
+        .style(default_style),
+        areas.chat_input,
+    );
+
+    // Reaction counts, and bursts that swell and fade as they age
+    let counts = REACTIONS
+        .iter()
+        .enumerate()
+        .map(|(kind, emoji)| {
+            let count = app.state.reactions.get(kind).copied().unwrap_or(0);
+            format!("{} {}", emoji, count)
+        })
+        .collect::<Vec<String>>()
+        .join("  ");
+    let bursts = app
+        .bursts
+        .iter()
+        .filter_map(|burst| {
+            let emoji = REACTIONS.get(burst.reaction.kind)?;
+            let size = [1, 2, 3, 3, 2, 2, 1, 1][burst.age as usize % BURST_TICKS as usize];
+            Some(format!("{} {}", burst.reaction.from, emoji.repeat(size)))
+        })
+        .collect::<Vec<String>>()
+        .join("  ");
+
+    // Audio progress bar, estimated from when the song came on air.
+    // A click swaps the percentage for the position and who queued the song
+    let position = app.song_position();
+    let ratio = match position {
+        Some((elapsed, Some(length))) => (elapsed.as_secs_f64() / length.as_secs_f64()).min(1.0),
+        _ => 0.0,
+    };
+    let label = match (position, app.now_playing.as_ref()) {
+        (Some((elapsed, length)), Some(entry)) if app.show_position => format!(
+            "{} / {}  {}  queued by {}",
+            format_duration(elapsed),
+            length.map_or("?".to_string(), format_duration),
+            entry.title,
+            entry.queued_by
+        ),
+        _ => format!("{:.0}%", ratio * 100.0),
+    };
     frame.render_widget(
         Gauge::default()
+            .gauge_style(theme.accent_style())
+            .ratio(ratio)
+            .label(label)
             .block(
-                Block::bordered()
+                block(&theme)
                     .title("Song progress")
-                    .border_type(BorderType::Rounded)
+                    .title(Line::from(counts).right_aligned())
+                    .title_bottom(Line::from(bursts).centered())
+                    .title_bottom(
+                        Line::from(format!(
+                            "[{}-{}] react",
+                            key(&app.keymap, Action::React(0)),
+                            key(&app.keymap, Action::React(4))
+                        ))
+                        .right_aligned(),
+                    )
+                    .title_alignment(Alignment::Center),
+            )
+            .style(default_style),
+        areas.gauge,
+    );
+
+    // Panel tabs of the compact layout
+    for (panel, area) in Panel::ALL.into_iter().zip(areas.tabs) {
+        let style = if panel == app.focus {
+            theme.highlight_style().reversed()
+        } else {
+            theme.accent_style()
+        };
+        frame.render_widget(
+            Paragraph::new(tab_label(panel)).style(style.bg(theme.background)),
+            area,
+        );
+    }
+
+    // Playlist import prompt
+    if let Some(import) = app.playlist_import.as_ref() {
+        let area = centered(frame.area(), 60, 7);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(format!(
+                "{} of {} songs aren't on the server yet.\n\n{}\n{}  {}",
+                import.missing.len(),
+                import.songs.len(),
+                hint(&app.keymap, Action::Confirm, "upload them and enqueue all"),
+                hint(&app.keymap, Action::Decline, "enqueue the others"),
+                hint(&app.keymap, Action::Cancel, "cancel")
+            ))
+            .block(
+                block(&theme)
+                    .title(format!("Import {}", import.name))
+                    .title_alignment(Alignment::Center),
+            )
+            .alignment(Alignment::Center)
+            .style(default_style),
+            area,
+        );
+    }
+
+    // Clear queue prompt
+    if app.confirm_clear_queue {
+        let area = centered(frame.area(), 50, 5);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(format!(
+                "Remove the {} upcoming songs for every listener?\n\n{}  {}",
+                app.state.song_queue.len().saturating_sub(1),
+                hint(&app.keymap, Action::Confirm, "clear"),
+                hint(&app.keymap, Action::Decline, "keep")
+            ))
+            .block(
+                block(&theme)
+                    .title("Clear queue")
                     .title_alignment(Alignment::Center),
             )
+            .alignment(Alignment::Center)
             .style(default_style),
-        functional_layout[1],
+            area,
+        );
+    }
+
+    // Help, generated from the active keymap
+    if let Some(scroll) = app.help_scroll {
+        let lines: Vec<Line> = ACTIONS
+            .iter()
+            .map(|(action, _, description)| {
+                let keys = app.keymap.keys(*action);
+                let keys = if keys.is_empty() {
+                    "-".to_string()
+                } else {
+                    keys.join(", ")
+                };
+                Line::from(vec![
+                    Span::raw(column(&keys, 20)).bold(),
+                    Span::raw(*description),
+                ])
+            })
+            .collect();
+        let area = centered(frame.area(), 72, frame.area().height.saturating_sub(4));
+        let visible = area.height.saturating_sub(2) as usize;
+        let scroll = scroll.min(lines.len().saturating_sub(visible));
+        app.help_scroll = Some(scroll);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines)
+                .scroll((scroll as u16, 0))
+                .block(
+                    block(&theme)
+                        .title(format!("Keys ({} preset)", app.keymap.preset))
+                        .title_bottom(
+                            Line::from(format!(
+                                "{} {}",
+                                scroll_hint(&app.keymap),
+                                hint(&app.keymap, Action::Help, "close")
+                            ))
+                            .right_aligned(),
+                        )
+                        .title_alignment(Alignment::Center),
+                )
+                .style(default_style),
+            area,
+        );
+    }
+
+    // Status bar, the latest notification and where we're connected
+    let status = app.status.as_ref().map_or(vec![], |status| {
+        let (label, style) = severity(&theme, status.severity);
+        vec![
+            Span::styled(format!(" {} ", label), style.bold()),
+            Span::raw(status.text.as_str()),
+        ]
+    });
+    let mut connection = format!("{}@{} ", app.nickname, app.server);
+    if app.zoomed {
+        connection.insert_str(0, &format!("zoomed [{}]  ", key(&app.keymap, Action::Zoom)));
+    }
+    let pending = describe_sequence(&app.pending_keys);
+    if !pending.is_empty() {
+        connection.insert_str(0, &format!("{} …  ", pending));
+    }
+    let status_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints(vec![
+            Constraint::Fill(1),
+            Constraint::Length(connection.chars().count() as u16),
+        ])
+        .split(areas.status);
+    frame.render_widget(
+        Paragraph::new(Line::from(status)).style(default_style),
+        status_layout[0],
     );
+    frame.render_widget(
+        Paragraph::new(connection)
+            .style(theme.accent_style().bg(theme.background))
+            .alignment(Alignment::Right),
+        status_layout[1],
+    );
+
+    // Toasts, stacked in the top right corner over everything else
+    // Long messages wrap over up to three lines
+    let screen = Rect {
+        height: frame.area().height.saturating_sub(areas.status.height),
+        ..frame.area()
+    };
+    let width = screen.width.min(50);
+    let inner = width.saturating_sub(2).max(1) as usize;
+    let mut top = screen.y;
+    for toast in app.toasts.iter().take(TOAST_LIMIT) {
+        let lines = toast.text.chars().count().div_ceil(inner);
+        let height = lines.clamp(1, 3) as u16 + 2;
+        let area = Rect::new(screen.right() - width, top, width, height).intersection(screen);
+        top += height;
+        let (title, style) = severity(&theme, toast.severity);
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(toast.text.as_str())
+                .wrap(Wrap { trim: true })
+                .block(
+                    block(&theme)
+                        .title(title)
+                        .title_style(style.bold())
+                        .border_style(style),
+                )
+                .style(default_style),
+            area,
+        );
+    }
 }
@@ -0,0 +1,151 @@
+#include "codec.hpp"
+#include <algorithm>
+#include <arpa/inet.h>
+#include <cstdint>
+#include <cstring>
+#include <fstream>
+#include <sstream>
+#include <stdexcept>
+#include <string>
+#include <vector>
+
+OpusStream::OpusStream(int bitrate) {
+#ifdef HAVE_OPUS
+  int error;
+  encoder = opus_encoder_create(sample_rate, channels, OPUS_APPLICATION_AUDIO,
+                                &error);
+  if (error != OPUS_OK) {
+    throw std::runtime_error("Opus encoder creation failed");
+  }
+  if (bitrate <= 0) {
+    bitrate = 64000;
+  }
+  bitrate = std::clamp(bitrate, 6000, 510000);
+  opus_encoder_ctl(encoder, OPUS_SET_BITRATE(bitrate));
+#else
+  (void)bitrate;
+  throw std::runtime_error("Server built without opus support");
+#endif
+}
+
+OpusStream::~OpusStream() {
+#ifdef HAVE_OPUS
+  opus_encoder_destroy(encoder);
+#endif
+}
+
+std::vector<char> OpusStream::encode(const char *pcm_data, size_t size) {
+  std::vector<char> packets;
+#ifdef HAVE_OPUS
+  pending.insert(pending.end(), pcm_data, pcm_data + size);
+
+  size_t consumed = 0;
+  std::vector<opus_int16> pcm(frame_size * channels);
+  unsigned char packet[max_packet];
+  while (pending.size() - consumed >= frame_bytes) {
+    std::memcpy(pcm.data(), pending.data() + consumed, frame_bytes);
+    consumed += frame_bytes;
+
+    int packet_size =
+        opus_encode(encoder, pcm.data(), frame_size, packet, max_packet);
+    if (packet_size < 0) {
+      continue;
+    }
+    uint16_t size = htons(static_cast<uint16_t>(packet_size));
+    packets.insert(packets.end(), reinterpret_cast<char *>(&size),
+                   reinterpret_cast<char *>(&size) + sizeof(size));
+    packets.insert(packets.end(), packet, packet + packet_size);
+  }
+  pending.erase(pending.begin(), pending.begin() + consumed);
+#else
+  (void)pcm_data;
+  (void)size;
+#endif
+  return packets;
+}
+
+void OpusStream::reset() { pending.clear(); }
+
+std::vector<char> OpusStream::passthrough(const std::vector<char> &chunk) {
+  std::vector<char> frames;
+  const size_t max_slice = raw_frame - 1;
+  for (size_t offset = 0; offset < chunk.size(); offset += max_slice) {
+    size_t slice = std::min(max_slice, chunk.size() - offset);
+    uint16_t size = htons(static_cast<uint16_t>(slice) | raw_frame);
+    frames.insert(frames.end(), reinterpret_cast<char *>(&size),
+                  reinterpret_cast<char *>(&size) + sizeof(size));
+    frames.insert(frames.end(), chunk.begin() + offset,
+                  chunk.begin() + offset + slice);
+  }
+  return frames;
+}
+
+// WAV fields are little endian
+static uint32_t le32(const char *bytes) {
+  const unsigned char *b = reinterpret_cast<const unsigned char *>(bytes);
+  return b[0] | b[1] << 8 | b[2] << 16 | static_cast<uint32_t>(b[3]) << 24;
+}
+
+static uint16_t le16(const char *bytes) {
+  const unsigned char *b = reinterpret_cast<const unsigned char *>(bytes);
+  return b[0] | b[1] << 8;
+}
+
+std::optional<std::pair<long, long>>
+OpusStream::pcmRange(const std::string &path) {
+  std::ifstream file(path, std::ios::binary);
+  char riff[12];
+  if (!file.read(riff, sizeof(riff)) || std::memcmp(riff, "RIFF", 4) != 0 ||
+      std::memcmp(riff + 8, "WAVE", 4) != 0) {
+    return std::nullopt;
+  }
+  // Header chunks (LIST, fact, ...) may come before or after the samples
+  bool canonical = false;
+  char header[8];
+  while (file.read(header, sizeof(header))) {
+    uint32_t size = le32(header + 4);
+    long body = file.tellg();
+    if (std::memcmp(header, "fmt ", 4) == 0) {
+      char fmt[16];
+      if (size < sizeof(fmt) || !file.read(fmt, sizeof(fmt))) {
+        return std::nullopt;
+      }
+      canonical = le16(fmt) == 1 && le16(fmt + 2) == channels &&
+                  le32(fmt + 4) == sample_rate && le16(fmt + 14) == 16;
+    } else if (std::memcmp(header, "data", 4) == 0) {
+      if (!canonical) {
+        return std::nullopt;
+      }
+      return std::make_pair(body, body + static_cast<long>(size));
+    }
+    // Chunks are padded to an even size
+    file.seekg(body + size + (size & 1), std::ios::beg);
+  }
+  return std::nullopt;
+}
+
+std::vector<std::string> Codec::supportedCodecs() {
+#ifdef HAVE_OPUS
+  return {"opus", "raw"};
+#else
+  return {"raw"};
+#endif
+}
+
+std::string Codec::negotiate(const std::string &offered) {
+  std::vector<std::string> supported = supportedCodecs();
+  std::stringstream codecs(offered);
+  std::string codec;
+  while (std::getline(codecs, codec, ',')) {
+    if (std::find(supported.begin(), supported.end(), codec) !=
+        supported.end()) {
+      return codec;
+    }
+  }
+  // Every client understands the raw stream
+  return "raw";
+}
+
+std::vector<char> Codec::marker(const std::string &codec) {
+  return {'J', 'A', 'M', codec.empty() ? 'r' : codec[0]};
+}
@@ -0,0 +1,68 @@
+#ifndef CODEC_HPP
+#define CODEC_HPP
+
+#include <cstdint>
+#include <optional>
+#include <string>
+#include <utility>
+#include <vector>
+
+#ifdef HAVE_OPUS
+#include <opus.h>
+#endif
+
+// Audio transports a client can ask for in the handshake
+// raw  -> slices of the original song file
+// opus -> [packet_size 2B | packet var] frames of the canonical PCM format
+//         (s16le, 48 kHz, stereo), songs in any other format are passed
+//         through as [0x8000 | slice_size 2B | raw slice var] frames
+class OpusStream {
+private:
+  static const int sample_rate = 48000;
+  static const int channels = 2;
+  static const int frame_size = 960; // 20 ms per channel
+  static const int frame_bytes = frame_size * channels * 2;
+  static const int max_packet = 4000; // recommended by libopus
+  static const uint16_t raw_frame = 0x8000;
+
+  // PCM waiting for a full frame
+  std::vector<char> pending;
+#ifdef HAVE_OPUS
+  OpusEncoder *encoder;
+#endif
+
+public:
+  OpusStream(int bitrate);
+  ~OpusStream();
+
+  OpusStream(const OpusStream &) = delete;
+  OpusStream &operator=(const OpusStream &) = delete;
+
+  // Encodes PCM samples into length prefixed opus packets
+  std::vector<char> encode(const char *pcm, size_t size);
+
+  // Drops PCM left over from the previous song
+  void reset();
+
+  // Frames a slice of a song that can't be encoded
+  static std::vector<char> passthrough(const std::vector<char> &chunk);
+
+  // Byte range of the samples in a WAV file of the canonical PCM format,
+  // nullopt for every other file
+  static std::optional<std::pair<long, long>>
+  pcmRange(const std::string &path);
+};
+
+namespace Codec {
+// Codecs compiled into this server, most preferred first
+std::vector<std::string> supportedCodecs();
+
+// Picks the first codec from the clients list the server supports
+std::string negotiate(const std::string &offered);
+
+// First bytes of a client's audio stream, "JAM" and the codec's initial.
+// Nothing is streamed before it, so clients know how to read what follows.
+std::vector<char> marker(const std::string &codec);
+} // namespace Codec
+
+#endif // CODEC_HPP
@@ -1,5 +1,6 @@
 // json.cpp
 #include "json.hpp"
+#include <cstdio>
 
 // Default constructor
 Json::Json() : type(Type::Null) {}
@@ -121,6 +122,39 @@ Json &Json::operator=(const Array &value) {
   arrayValue = value;
   return *this;
 }
+// Escapes quotes, backslashes and control characters
+static std::string escape(const std::string &value) {
+  std::ostringstream oss;
+  for (unsigned char c : value) {
+    switch (c) {
+    case '"':
+      oss << "\\\"";
+      break;
+    case '\\':
+      oss << "\\\\";
+      break;
+    case '\n':
+      oss << "\\n";
+      break;
+    case '\r':
+      oss << "\\r";
+      break;
+    case '\t':
+      oss << "\\t";
+      break;
+    default:
+      if (c < 0x20) {
+        char code[7];
+        snprintf(code, sizeof(code), "\\u%04x", c);
+        oss << code;
+      } else {
+        oss << c;
+      }
+    }
+  }
+  return oss.str();
+}
+
 // Serialization
 std::string Json::toString() const {
   switch (type) {
@@ -131,7 +165,7 @@ std::string Json::toString() const {
   case Type::Number:
     return std::to_string(numberValue);
   case Type::String:
-    return '"' + stringValue + '"';
+    return '"' + escape(stringValue) + '"';
   case Type::Object: {
     std::ostringstream oss;
     oss << "{";
@@ -139,7 +173,7 @@ std::string Json::toString() const {
       if (it != objectValue.begin()) {
         oss << ",";
       }
-      oss << '"' << it->first << "\" : " << it->second.toString();
+      oss << '"' << escape(it->first) << "\" : " << it->second.toString();
     }
     oss << "}";
     return oss.str();
@@ -1,5 +1,6 @@
 #include "queue.hpp"
 #include "json.hpp"
+#include <algorithm>
 #include <deque>
 #include <fstream>
 #include <ios>
@@ -7,6 +8,7 @@
 #include <mutex>
 #include <shared_mutex>
 #include <stdexcept>
+#include <string>
 #include <vector>
 
 Queue::Queue() {};
@@ -24,10 +26,55 @@ Json::Array Queue::getJsonQueue() {
   return Json::Array(songs);
 }
 
-void Queue::addToQueue(std::string path) {
+Json::Array Queue::getJsonVotes() {
+  std::shared_lock<std::shared_mutex> lock(Queue::queue_mutex);
+
+  std::vector<Json> votes;
+
+  for (const auto &song : song_queue) {
+    Json tally;
+    tally["up"] = Json(static_cast<int>(song.upvotes.size()));
+    tally["down"] = Json(static_cast<int>(song.downvotes.size()));
+    votes.push_back(tally);
+  }
+  return Json::Array(votes);
+}
+
+int Queue::getSkipVotes() {
+  std::shared_lock<std::shared_mutex> lock(queue_mutex);
+  if (song_queue.empty()) {
+    return 0;
+  }
+  return song_queue.front().skip_votes.size();
+}
+
+Json::Array Queue::getJsonReactions() {
+  std::shared_lock<std::shared_mutex> lock(Queue::queue_mutex);
+
+  std::vector<Json> counts;
+
+  for (int kind = 0; kind < reaction_kinds; ++kind) {
+    counts.push_back(
+        Json(song_queue.empty() ? 0 : song_queue.front().reactions[kind]));
+  }
+  return Json::Array(counts);
+}
+
+Json::Array Queue::getJsonQueuedBy() {
+  std::shared_lock<std::shared_mutex> lock(Queue::queue_mutex);
+
+  std::vector<Json> names;
+
+  for (const auto &song : song_queue) {
+    names.push_back(Json(song.queued_by));
+  }
+  return Json::Array(names);
+}
+
+void Queue::addToQueue(std::string path, std::string queued_by) {
   std::unique_lock<std::shared_mutex> lock(queue_mutex);
-  Song song(path);
-  song_queue.push_front(song);
+  Song song(path, queued_by);
+  song_queue.push_back(song);
 }
 
 std::deque<Queue::Song> Queue::getQueue() {
@@ -40,42 +87,146 @@ bool Queue::isEmpty() {
   return Queue::song_queue.empty();
 }
 
-std::vector<char> Queue::getChunk() {
-  std::shared_lock<std::shared_mutex> lock(Queue::queue_mutex);
-  int left = -1;
-  int chunk = chunk_size;
-  int cursor = song_queue.front().cursor;
-  std::vector<char> audioChunk(chunk_size);
-  if (song_queue.front().file_size - cursor <= chunk_size) {
-    left = song_queue.front().file_size - cursor;
-    if (left <= 0) {
+size_t Queue::size() {
+  std::shared_lock<std::shared_mutex> lock(queue_mutex);
+  return song_queue.size();
+}
+
+void Queue::remove(size_t index) {
+  std::unique_lock<std::shared_mutex> lock(queue_mutex);
+  // The current song only leaves through a skip vote
+  if (index > 0 && index < song_queue.size()) {
+    song_queue.erase(song_queue.begin() + index);
+  }
+}
+
+void Queue::move(size_t index, int direction) {
+  std::unique_lock<std::shared_mutex> lock(queue_mutex);
+  size_t target = index + direction;
+  // The current song stays where it is
+  if (index == 0 || target == 0 || index >= song_queue.size() ||
+      target >= song_queue.size()) {
+    return;
+  }
+  std::swap(song_queue[index], song_queue[target]);
+}
+
+void Queue::playNext(size_t index) {
+  std::unique_lock<std::shared_mutex> lock(queue_mutex);
+  if (index <= 1 || index >= song_queue.size()) {
+    return;
+  }
+  Song song = song_queue[index];
+  song_queue.erase(song_queue.begin() + index);
+  song_queue.insert(song_queue.begin() + 1, song);
+}
+
+void Queue::clear() {
+  std::unique_lock<std::shared_mutex> lock(queue_mutex);
+  if (song_queue.size() > 1) {
+    song_queue.erase(song_queue.begin() + 1, song_queue.end());
+  }
+}
+
+void Queue::sortByVotes() {
+  if (song_queue.size() < 3) {
+    return;
+  }
+  auto score = [](const Song &song) {
+    return static_cast<int>(song.upvotes.size()) -
+           static_cast<int>(song.downvotes.size());
+  };
+  std::stable_sort(
+      song_queue.begin() + 1, song_queue.end(),
+      [&](const Song &a, const Song &b) { return score(a) > score(b); });
+}
+
+void Queue::vote(size_t index, int fd, int direction) {
+  std::unique_lock<std::shared_mutex> lock(queue_mutex);
+  // The current song can only be voted off with a skip vote
+  if (index == 0 || index >= song_queue.size()) {
+    return;
+  }
+  Song &song = song_queue[index];
+  std::set<int> &chosen = direction > 0 ? song.upvotes : song.downvotes;
+  std::set<int> &other = direction > 0 ? song.downvotes : song.upvotes;
+  other.erase(fd);
+  if (!chosen.erase(fd)) {
+    chosen.insert(fd);
+  }
+  sortByVotes();
+}
+
+std::optional<std::string> Queue::voteSkip(int fd, size_t needed) {
+  std::unique_lock<std::shared_mutex> lock(queue_mutex);
+  if (song_queue.empty()) {
+    return std::nullopt;
+  }
+  std::set<int> &votes = song_queue.front().skip_votes;
+  if (!votes.erase(fd)) {
+    votes.insert(fd);
+  }
+  if (votes.size() < needed) {
+    return std::nullopt;
+  }
+  std::string skipped = song_queue.front().path.data() + 8;
+  song_queue.pop_front();
+  return skipped;
+}
+
+bool Queue::react(int kind) {
+  std::unique_lock<std::shared_mutex> lock(queue_mutex);
+  if (song_queue.empty() || kind < 0 || kind >= reaction_kinds) {
+    return false;
+  }
+  song_queue.front().reactions[kind]++;
+  return true;
+}
+
+void Queue::dropVotes(int fd) {
+  std::unique_lock<std::shared_mutex> lock(queue_mutex);
+  for (auto &song : song_queue) {
+    song.upvotes.erase(fd);
+    song.downvotes.erase(fd);
+    song.skip_votes.erase(fd);
+  }
+  sortByVotes();
+}
+
+Queue::Chunk Queue::getChunk() {
+  // Advances the cursor, so it needs exclusive access like the other edits
+  std::unique_lock<std::shared_mutex> lock(Queue::queue_mutex);
+  if (song_queue.empty()) {
+    return {};
+  }
+  Song &song = song_queue.front();
+  // The last chunk of a song is shorter, songs are never mixed in one chunk
+  // so a skip or reorder always starts the next song cleanly
+  int chunk = std::min(chunk_size, song.file_size - song.cursor);
+  Chunk audioChunk;
+  audioChunk.song_start = song.cursor == 0;
+  audioChunk.data.resize(std::max(chunk, 0));
+  if (chunk > 0) {
+    std::ifstream file(song.path, std::ios::binary);
+    if (!file.is_open()) {
+      // Left at the front it would fail again on every chunk
+      std::string skipped = song.path.data() + 8;
       song_queue.pop_front();
-    } else {
-      std::ifstream s1(song_queue.front().path, std::ios::binary);
-      if (!s1.is_open()) {
-        throw std::runtime_error("Failed during song file open");
-      }
-      s1.seekg(cursor, std::ios::beg);
-      s1.read(audioChunk.data(), left);
-      s1.close();
+      throw std::runtime_error("Skipped " + skipped + ", the file is gone");
     }
-  }
-  std::ifstream song(song_queue.front().path, std::ios::binary);
-  if (!song.is_open()) {
-    if (!song_queue.empty()) {
-      throw std::runtime_error("Failed during song file open");
+    file.seekg(song.cursor, std::ios::beg);
+    file.read(audioChunk.data.data(), chunk);
+    if (song.pcm) {
+      audioChunk.pcm = true;
+      audioChunk.pcm_begin =
+          std::clamp<long>(song.pcm->first - song.cursor, 0, chunk);
+      audioChunk.pcm_end = std::clamp<long>(song.pcm->second - song.cursor,
+                                            audioChunk.pcm_begin, chunk);
     }
+    song.cursor += chunk;
   }
-  if (left > 0) {
-    chunk -= left;
-    song.read(audioChunk.data() + left, chunk);
-    song.close();
-    song_queue.front().cursor += left;
-  } else {
-    song.seekg(cursor);
-    song.read(audioChunk.data(), chunk);
-    song.close();
-    song_queue.front().cursor += chunk;
+  if (song.cursor >= song.file_size) {
+    song_queue.pop_front();
   }
   return audioChunk;
 }
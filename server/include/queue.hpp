@@ -1,15 +1,25 @@
 #ifndef QUEUE_HPP
 #define QUEUE_HPP
 
+#include "codec.hpp"
 #include "json.hpp"
+#include <array>
 #include <cstdio>
 #include <deque>
 #include <fstream>
+#include <optional>
+#include <set>
 #include <shared_mutex>
 #include <stdexcept>
 #include <string>
+#include <utility>
+#include <vector>
 
 class Queue {
+public:
+  // Reactions a client can send, the client maps them to emoji
+  static const int reaction_kinds = 5;
+
 private:
   mutable std::shared_mutex queue_mutex;
   const int chunk_size = 10000; // 50 ms of audio -> 16 bit (2B precision) = 4B,
@@ -18,32 +28,94 @@ private:
     std::string path;
     int file_size;
     int cursor; // amount of bytes already sent
+    std::string queued_by; // nickname of the listener who queued it
+    // Client fds that voted, a listener counts once per song
+    std::set<int> upvotes;
+    std::set<int> downvotes;
+    std::set<int> skip_votes;
+    // Reaction counts while the song is on air
+    std::array<int, reaction_kinds> reactions{};
+    // Where the samples are when opus clients can get the song encoded
+    std::optional<std::pair<long, long>> pcm;
 
-    Song(const std::string &file_path)
-        : path(file_path), file_size(0), cursor(0) {
+    Song(const std::string &file_path, const std::string &queued_by)
+        : path(file_path), file_size(0), cursor(0), queued_by(queued_by) {
       std::ifstream song(file_path, std::ios::binary | std::ios::ate);
       if (!song.is_open()) {
         throw std::runtime_error("Failed to open song file");
       }
       file_size = static_cast<int>(song.tellg());
+      pcm = OpusStream::pcmRange(file_path);
     }
   };
 
   std::deque<Song> song_queue;
 
+  // Upcoming songs ordered by score, ties keep their queue order
+  void sortByVotes();
+
 public:
+  // Slice of the current song, songs are never mixed in one chunk
+  struct Chunk {
+    std::vector<char> data;
+    bool song_start = false;
+    // Set for songs in the canonical PCM format, data[pcm_begin, pcm_end)
+    // holds samples and the rest is WAV header
+    bool pcm = false;
+    size_t pcm_begin = 0;
+    size_t pcm_end = 0;
+  };
+
   Queue();
   // Communication
   Json::Array getJsonQueue();
 
-  void addToQueue(std::string path);
+  // Vote tallies in queue order [{"up": n, "down": n}]
+  Json::Array getJsonVotes();
+
+  int getSkipVotes();
+
+  // Reaction counts of the current song, one per kind
+  Json::Array getJsonReactions();
+
+  // Nicknames in queue order
+  Json::Array getJsonQueuedBy();
+
+  void addToQueue(std::string path, std::string queued_by);
 
   std::deque<Song> getQueue();
 
   bool isEmpty();
 
+  size_t size();
+
+  // Management (index 0 is the song currently playing)
+  void remove(size_t index);
+
+  // Moves a song one place up (-1) or down (1), never above the current one
+  void move(size_t index, int direction);
+
+  // Moves a song right behind the current one
+  void playNext(size_t index);
+
+  // Drops everything except the current song
+  void clear();
+
+  // Voting, a second identical vote takes it back
+  // Up (1) or down (-1) vote on an upcoming song
+  void vote(size_t index, int fd, int direction);
+
+  // Returns the skipped song when the vote reached `needed`
+  std::optional<std::string> voteSkip(int fd, size_t needed);
+
+  // Counts a reaction to the current song, false when nothing is playing
+  bool react(int kind);
+
+  // Forgets the votes of a disconnected client
+  void dropVotes(int fd);
+
   // Streaming
-  std::vector<char> getChunk();
+  Chunk getChunk();
 };
 
 #endif // !QUEUE_HPP
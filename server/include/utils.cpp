@@ -2,14 +2,58 @@
 #include "json.hpp"
 #include <filesystem>
 #include <fstream>
+#include <cstdlib>
 #include <iostream>
+#include <cerrno>
+#include <cstdint>
+#include <cstdio>
 #include <netinet/in.h>
+#include <poll.h>
 #include <string>
+#include <sys/socket.h>
 #include <unistd.h>
 #include <vector>
 
 Utils::Utils() {};
 
+// Metadata files hold one key=value per line, so tags can't contain raw
+// newlines or separators
+static std::string escapeValue(const std::string &value) {
+  std::string escaped;
+  for (char c : value) {
+    switch (c) {
+    case '\\':
+      escaped += "\\\\";
+      break;
+    case '\n':
+      escaped += "\\n";
+      break;
+    case '\r':
+      escaped += "\\r";
+      break;
+    case '=':
+      escaped += "\\=";
+      break;
+    default:
+      escaped += c;
+    }
+  }
+  return escaped;
+}
+
+static std::string unescapeValue(const std::string &value) {
+  std::string unescaped;
+  for (size_t i = 0; i < value.size(); i++) {
+    if (value[i] != '\\' || i + 1 == value.size()) {
+      unescaped += value[i];
+      continue;
+    }
+    char c = value[++i];
+    unescaped += c == 'n' ? '\n' : c == 'r' ? '\r' : c;
+  }
+  return unescaped;
+}
+
 Json::Array Utils::getSongLibrary() {
 
   std::vector<Json> songs;
@@ -17,12 +61,94 @@ Json::Array Utils::getSongLibrary() {
   for (const auto &entry :
        std::filesystem::directory_iterator(song_library_path)) {
     std::string song = entry.path().string().erase(0, song_library_path.size());
+    // Hidden files aren't songs
+    if (song.empty() || song[0] == '.') {
+      continue;
+    }
     songs.push_back(Json(song));
   }
 
   return Json::Array(songs);
 }
 
+Json::Object Utils::getSongHashes() {
+  std::lock_guard<std::mutex> lock(hash_mutex);
+  Json::Object hashes;
+  std::map<std::string, CachedHash> cache;
+
+  for (const auto &entry :
+       std::filesystem::directory_iterator(song_library_path)) {
+    std::string song = entry.path().filename().string();
+    if (song.empty() || song[0] == '.' || !entry.is_regular_file()) {
+      continue;
+    }
+    CachedHash current{entry.file_size(), entry.last_write_time(), ""};
+    auto cached = hash_cache.find(song);
+    if (cached != hash_cache.end() && cached->second.size == current.size &&
+        cached->second.modified == current.modified) {
+      current.hash = cached->second.hash;
+    } else {
+      current.hash = hashFile(entry.path().string());
+    }
+    hashes[song] = Json(current.hash);
+    cache[song] = current;
+  }
+  // Songs removed from the library drop out of the cache
+  hash_cache = cache;
+
+  return hashes;
+}
+
+Json::Object Utils::getSongMetadata() {
+  Json::Object metadata;
+
+  if (!std::filesystem::is_directory(metadata_path)) {
+    return metadata;
+  }
+
+  for (const auto &entry :
+       std::filesystem::directory_iterator(metadata_path)) {
+    std::ifstream file(entry.path());
+    Json song;
+    std::string line;
+    while (std::getline(file, line)) {
+      size_t separator = line.find('=');
+      if (separator == std::string::npos) {
+        continue;
+      }
+      std::string key = line.substr(0, separator);
+      std::string value = line.substr(separator + 1);
+      if (key == "length") {
+        song[key] = Json(std::atoi(value.c_str()));
+      } else {
+        song[key] = Json(unescapeValue(value));
+      }
+    }
+    metadata[entry.path().filename().string()] = song;
+  }
+
+  return metadata;
+}
+
+bool Utils::saveSongMetadata(const std::string &song_name,
+                             const std::string &artist,
+                             const std::string &title, const std::string &album,
+                             int length) {
+  // Only the file name, clients send the path they uploaded to
+  std::string name = std::filesystem::path(song_name).filename().string();
+  if (name.empty() || name[0] == '.' ||
+      !std::filesystem::is_regular_file(song_library_path + name)) {
+    return false;
+  }
+  std::filesystem::create_directories(metadata_path);
+  std::ofstream file(metadata_path + name);
+  file << "artist=" << escapeValue(artist) << "\n";
+  file << "title=" << escapeValue(title) << "\n";
+  file << "album=" << escapeValue(album) << "\n";
+  file << "length=" << length << "\n";
+  return true;
+}
+
 void Utils::addSongToLibrary(char *file_name, char *file_content) {
   std::ofstream newSong(file_name);
 
@@ -33,20 +159,98 @@ void Utils::addSongToLibrary(char *file_name, char *file_content) {
 
 bool Utils::readFully(int fd, char *buffer, size_t size) {
   size_t total_read = 0;
-  size_t chunk_size = 4096;
   while (total_read < size) {
-    if (size - total_read < chunk_size) {
-      chunk_size = size - total_read;
+    ssize_t bytes_read = read(fd, buffer + total_read, size - total_read);
+    if (bytes_read < 0) {
+      if (errno == EINTR) {
+        continue;
+      }
+      if (errno == EAGAIN || errno == EWOULDBLOCK) {
+        // The rest may still be on its way from a slow client
+        pollfd readable{fd, POLLIN, 0};
+        if (poll(&readable, 1, 5000) <= 0) {
+          return false;
+        }
+        continue;
+      }
+      perror("Read error");
+      return false;
     }
-    size_t bytes_read = read(fd, buffer + total_read, chunk_size);
-    return false;
-    if (bytes_read <= 0) {
-      if (bytes_read < 0)
-        perror("Read error");
+    if (bytes_read == 0) {
+      // Closed before everything arrived
       return false;
     }
     total_read += bytes_read;
-    std::cout << total_read << std::endl;
   }
   return true;
 }
+
+bool Utils::sendAll(int fd, const char *data, size_t size) {
+  size_t sent = 0;
+  while (sent < size) {
+    ssize_t bytes_sent = send(fd, data + sent, size - sent, MSG_NOSIGNAL);
+    if (bytes_sent < 0) {
+      if (errno == EINTR) {
+        continue;
+      }
+      if (errno == EAGAIN || errno == EWOULDBLOCK) {
+        // Give a slow client a few seconds to drain its socket
+        pollfd writable{fd, POLLOUT, 0};
+        if (poll(&writable, 1, 5000) <= 0) {
+          return false;
+        }
+        continue;
+      }
+      perror("Send error");
+      return false;
+    }
+    sent += bytes_sent;
+  }
+  return true;
+}
+
+std::string Utils::hashFile(const std::string &path) {
+  uint64_t hash = 0xcbf29ce484222325ULL;
+  // Read in blocks, songs may not fit in memory
+  std::ifstream file(path, std::ios::binary);
+  std::vector<char> block(64 * 1024);
+  while (file.read(block.data(), block.size()) || file.gcount() > 0) {
+    for (std::streamsize i = 0; i < file.gcount(); ++i) {
+      hash ^= static_cast<uint8_t>(block[i]);
+      hash *= 0x100000001b3ULL;
+    }
+  }
+  char hex[17];
+  snprintf(hex, sizeof(hex), "%016llx", static_cast<unsigned long long>(hash));
+  return hex;
+}
+
+std::string Utils::base64(const char *data, size_t size) {
+  static const char alphabet[] =
+      "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+  std::string encoded;
+  encoded.reserve((size + 2) / 3 * 4);
+  for (size_t i = 0; i < size; i += 3) {
+    uint32_t triple = static_cast<uint8_t>(data[i]) << 16;
+    if (i + 1 < size)
+      triple |= static_cast<uint8_t>(data[i + 1]) << 8;
+    if (i + 2 < size)
+      triple |= static_cast<uint8_t>(data[i + 2]);
+    encoded += alphabet[(triple >> 18) & 63];
+    encoded += alphabet[(triple >> 12) & 63];
+    encoded += i + 1 < size ? alphabet[(triple >> 6) & 63] : '=';
+    encoded += i + 2 < size ? alphabet[triple & 63] : '=';
+  }
+  return encoded;
+}
+
+std::string Utils::truncate(const std::string &text, size_t size) {
+  if (text.size() <= size) {
+    return text;
+  }
+  // Back up over continuation bytes (10xxxxxx) to the start of a character
+  while (size > 0 && (static_cast<unsigned char>(text[size]) & 0xC0) == 0x80) {
+    size--;
+  }
+  return text.substr(0, size);
+}
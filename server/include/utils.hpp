@@ -3,6 +3,8 @@
 
 #include "json.hpp"
 #include <filesystem>
+#include <map>
+#include <mutex>
 #include <string>
 #include <vector>
 
@@ -10,16 +12,51 @@ class Utils {
 
 private:
   std::string song_library_path = "songs/";
+  // One key=value file per song, named like the song
+  std::string metadata_path = "metadata/";
+
+  // Library hashes, recomputed when a song's size or mtime changes
+  struct CachedHash {
+    std::uintmax_t size;
+    std::filesystem::file_time_type modified;
+    std::string hash;
+  };
+  std::map<std::string, CachedHash> hash_cache;
+  std::mutex hash_mutex;
 
 public:
   Utils();
 
   Json::Array getSongLibrary();
 
+  // Content hash of every song, keyed by song name
+  Json::Object getSongHashes();
+
+  // Tags uploaded by clients, keyed by song name
+  Json::Object getSongMetadata();
+
+  // False for songs that aren't in the library
+  bool saveSongMetadata(const std::string &song_name, const std::string &artist,
+                        const std::string &title, const std::string &album,
+                        int length);
+
   void addSongToLibrary(char *file_name,
                         char *file_content); // Interpret buffer
 
+  // Reads exactly size bytes from a non blocking socket, waiting while it is
+  // empty. False when the client is gone or stays silent.
   bool readFully(int fd, char *buffer, size_t size);
+
+  // Writes everything to a non blocking socket, waiting while it is full
+  bool sendAll(int fd, const char *data, size_t size);
+
+  // FNV-1a 64 of a file as 16 hex digits, clients verify downloads with it
+  std::string hashFile(const std::string &path);
+
+  std::string base64(const char *data, size_t size);
+
+  // At most size bytes of text, without splitting a UTF-8 sequence
+  std::string truncate(const std::string &text, size_t size);
 };
 
 #endif // UTILS_HPP
@@ -1,19 +1,24 @@
+#include "codec.hpp"
 #include "json.hpp"
 #include "queue.hpp"
 #include "utils.hpp"
+#include <algorithm>
 #include <arpa/inet.h>
 #include <asm-generic/socket.h>
 #include <cerrno>
 #include <chrono>
 #include <condition_variable>
 #include <cstring>
+#include <ctime>
 #include <errno.h>
 #include <exception>
 #include <fcntl.h>
+#include <filesystem>
 #include <fstream>
 #include <functional>
 #include <ios>
 #include <iostream>
+#include <memory>
 #include <mutex>
 #include <netinet/in.h>
 #include <queue>
@@ -89,12 +94,27 @@ class ClientManager {
   struct Client {
     sockaddr_in client_address;
     int audio_fd;
+    std::string codec;
+    // Only set for opus clients, keeps encoder state between chunks
+    std::shared_ptr<OpusStream> encoder;
+    std::string nickname;
+    std::time_t connected_since;
+    // Audio waits for the handshake, the codec marker goes out first
+    bool streaming;
+    // Worker threads and the stream thread write to the same socket,
+    // whole messages must not interleave
+    std::shared_ptr<std::mutex> send_mutex;
 
     // Default constructor for std::map default initilization
-    Client() : client_address{}, audio_fd{-1} {};
+    Client()
+        : client_address{}, audio_fd{-1}, codec("raw"), nickname("anonymous"),
+          connected_since(std::time(nullptr)), streaming(false),
+          send_mutex(std::make_shared<std::mutex>()) {};
 
     Client(sockaddr_in client_address, int audio_fd)
-        : client_address(client_address), audio_fd(audio_fd) {};
+        : client_address(client_address), audio_fd(audio_fd), codec("raw"),
+          nickname("anonymous"), connected_since(std::time(nullptr)),
+          streaming(false), send_mutex(std::make_shared<std::mutex>()) {};
   };
 
   mutable std::shared_mutex clients_mutex;
@@ -113,12 +133,66 @@ public:
     close(fd);
   }
 
+  // Starts the client's audio in the codec, its marker must be sent already
+  void setCodec(int fd, const std::string &codec, int bitrate) {
+    std::unique_lock<std::shared_mutex> lock(clients_mutex);
+    auto it = clients.find(fd);
+    if (it == clients.end()) {
+      throw std::out_of_range("Client not found");
+    }
+    it->second.codec = codec;
+    it->second.streaming = true;
+    if (codec == "opus") {
+      it->second.encoder = std::make_shared<OpusStream>(bitrate);
+    } else {
+      it->second.encoder.reset();
+    }
+  }
+
+  void setNickname(int fd, const std::string &nickname) {
+    std::unique_lock<std::shared_mutex> lock(clients_mutex);
+    auto it = clients.find(fd);
+    if (it == clients.end()) {
+      throw std::out_of_range("Client not found");
+    }
+    it->second.nickname = nickname;
+  }
+
+  // Roster [{"nickname": .., "connected_since": unix seconds}]
+  Json::Array getJsonListeners() const {
+    std::shared_lock<std::shared_mutex> lock(clients_mutex);
+    std::vector<Json> listeners;
+    for (const auto &client : clients) {
+      Json listener;
+      listener["nickname"] = Json(client.second.nickname);
+      listener["connected_since"] =
+          Json(static_cast<int>(client.second.connected_since));
+      listeners.push_back(listener);
+    }
+    return Json::Array(listeners);
+  }
+
   int getActiveListeners() const {
     std::shared_lock<std::shared_mutex> lock(clients_mutex);
     return clients.size();
   };
 
-  const Client &getClient(int fd) {
+  // Lock held while a message is written to the client, empty if it is gone
+  std::unique_lock<std::mutex> lockSend(int fd) {
+    std::shared_ptr<std::mutex> send_mutex;
+    {
+      std::shared_lock<std::shared_mutex> lock(clients_mutex);
+      auto it = clients.find(fd);
+      if (it == clients.end()) {
+        return {};
+      }
+      send_mutex = it->second.send_mutex;
+    }
+    return std::unique_lock<std::mutex>(*send_mutex);
+  }
+
+  // Copies, so the entry can't be erased or changed while it is in use
+  Client getClient(int fd) const {
     std::shared_lock<std::shared_mutex> lock(clients_mutex);
     auto it = clients.find(fd);
 
@@ -128,7 +202,9 @@ public:
     return it->second;
   }
 
-  const std::map<int, Client> &getClients() const {
+  // Snapshot to iterate without holding the lock, encoders are shared so
+  // a codec change never frees one that is still encoding
+  std::map<int, Client> getClients() const {
     std::shared_lock<std::shared_mutex> lock(clients_mutex);
     return clients;
   }
@@ -144,18 +220,25 @@ class JamRadio {
   Queue queue;
   Utils utils;
   bool running;
+  // Share of the active listeners needed to skip the current song
+  int skip_percent;
 
 public:
-  JamRadio(int c_port, int a_port, size_t thread_count)
-      : threadPool(thread_count) {
+  JamRadio(int c_port, int a_port, size_t thread_count, int skip_percent = 50)
+      : threadPool(thread_count), skip_percent(skip_percent) {
     setup(c_port, a_port);
   }
 
+  // At least one vote, rounded up so half of three listeners is two
+  size_t skipVotesNeeded() const {
+    int listeners = clientManager.getActiveListeners();
+    int needed = (listeners * skip_percent + 99) / 100;
+    return std::max(needed, 1);
+  }
+
   ~JamRadio() {
-    if (clientManager.getClients().size() > 0) {
-      for (const auto &client : clientManager.getClients()) {
-        close(client.first);
-      }
+    for (const auto &client : clientManager.getClients()) {
+      close(client.first);
     }
     shutdown(server_fd, SHUT_WR);
     close(server_fd);
@@ -277,9 +360,12 @@ public:
     int ret = read(fd, &signature, sizeof(signature));
     if (ret <= 0) {
       if (ret == 0) {
+        std::string nickname = clientManager.getClient(fd).nickname;
         clientManager.removeClient(fd);
+        queue.dropVotes(fd);
         epoll_ctl(epoll_fd, EPOLL_CTL_DEL, fd, NULL);
         std::cout << "Client disconnected" << std::endl;
+        sendChat("", nickname + " left", true);
         sendUpdate();
       }
       return;
@@ -298,32 +384,49 @@ public:
       std::vector<char> filename(filename_size);
 
       read(fd, filename.data(), filename_size);
-
-      std::cout << "filename: " << filename.data() << std::endl;
+      // Clients may browse anywhere, only the file name decides where the
+      // song lands in the library
+      std::string song_path =
+          "songs/" + std::filesystem::path(
+                         std::string(filename.begin(), filename.end()))
+                         .filename()
+                         .string();
+
+      std::cout << "filename: " << song_path << std::endl;
       read(fd, &file_size, sizeof(file_size));
       file_size = ntohl(file_size);
 
       std::cout << "file_size: " << file_size << std::endl;
 
-      std::ofstream newSong(filename.data(), std::ios::binary);
+      std::ofstream newSong(song_path, std::ios::binary);
       if (!newSong.is_open()) {
         throw std::runtime_error("Song file writing error");
       };
 
-      uint32_t bytes_read;
       uint32_t total = 0;
       char audio_buffer[CHUNK_SIZE]{};
-      while ((bytes_read = read(fd, audio_buffer, CHUNK_SIZE))) {
-        newSong.write(audio_buffer, bytes_read);
-        std::cout << "Written " << bytes_read << " bytes" << std::endl;
-        total += bytes_read;
-        std::cout << "Total bytes read: " << total << std::endl;
-        if (total >= file_size) {
-          std::cout << filename.data() << " added to the song library"
-                    << std::endl;
+      while (total < file_size) {
+        // Never read past the song, the next message follows right after it
+        size_t size = std::min<size_t>(CHUNK_SIZE, file_size - total);
+        if (!utils.readFully(fd, audio_buffer, size)) {
           break;
         }
+        newSong.write(audio_buffer, size);
+        total += size;
       }
+      newSong.close();
+
+      if (total < file_size) {
+        std::cerr << "Upload of " << song_path << " broke off after " << total
+                  << " of " << file_size << " bytes" << std::endl;
+        std::filesystem::remove(song_path);
+        break;
+      }
+      std::cout << song_path << " added to the song library" << std::endl;
+      sendChat("",
+               clientManager.getClient(fd).nickname + " uploaded " +
+                   std::filesystem::path(song_path).filename().string(),
+               true);
 
       break;
     }
@@ -337,10 +440,210 @@ public:
 
       std::vector<char> songname(songname_size);
       read(fd, songname.data(), songname_size);
+      // Only songs from the library can be queued, whatever path was sent
+      std::string song_name =
+          std::filesystem::path(std::string(songname.begin(), songname.end()))
+              .filename()
+              .string();
+      std::string song_path = "./songs/" + song_name;
+
+      std::cout << "Song name: " << song_path << std::endl;
+
+      if (song_name.empty() || song_name[0] == '.' ||
+          !std::filesystem::is_regular_file(song_path)) {
+        sendNotice(fd, "Can't queue " + song_name + ": not in the library");
+        break;
+      }
+      try {
+        queue.addToQueue(song_path, clientManager.getClient(fd).nickname);
+      } catch (const std::exception &e) {
+        // The file may be gone since the check
+        std::cerr << "Queue error: " << e.what() << std::endl;
+        sendNotice(fd, "Can't queue " + song_name + ": " + e.what());
+      }
+      break;
+    }
+    case 'e': {
+      // Queue edit: operation 1B, queue index 4B
+      char operation;
+      uint32_t index;
+      read(fd, &operation, sizeof(operation));
+      read(fd, &index, sizeof(index));
+      index = ntohl(index);
+
+      std::cout << "Queue edit: " << operation << " at " << index << std::endl;
+      switch (operation) {
+      case 'r':
+        queue.remove(index);
+        break;
+      case 'u':
+        queue.move(index, -1);
+        break;
+      case 'd':
+        queue.move(index, 1);
+        break;
+      case 'n':
+        queue.playNext(index);
+        break;
+      case 'c':
+        queue.clear();
+        break;
+      }
+      break;
+    }
+    case 'v': {
+      // Vote: kind 1B ('s' skip, '+' up, '-' down), queue index 4B
+      char kind;
+      uint32_t index;
+      read(fd, &kind, sizeof(kind));
+      read(fd, &index, sizeof(index));
+      index = ntohl(index);
+
+      std::cout << "Vote: " << kind << " at " << index << std::endl;
+      if (kind == 's') {
+        if (auto skipped = queue.voteSkip(fd, skipVotesNeeded())) {
+          std::cout << "Skip vote passed" << std::endl;
+          sendChat("", "Vote passed, skipped " + *skipped, true);
+        }
+      } else if (kind == '+' || kind == '-') {
+        queue.vote(index, fd, kind == '+' ? 1 : -1);
+      }
+      break;
+    }
+    case 'm': {
+      std::cout << "Receiving song metadata" << std::endl;
+      auto readField = [fd]() {
+        uint32_t field_size;
+        read(fd, &field_size, sizeof(field_size));
+        field_size = ntohl(field_size);
+        std::string field(field_size, '\0');
+        read(fd, field.data(), field_size);
+        return field;
+      };
+      std::string songname = readField();
+      std::string artist = readField();
+      std::string title = readField();
+      std::string album = readField();
+      uint32_t length;
+      read(fd, &length, sizeof(length));
+      length = ntohl(length);
+
+      std::cout << "Metadata for: " << songname << std::endl;
+      if (!utils.saveSongMetadata(songname, artist, title, album,
+                                  static_cast<int>(length))) {
+        std::cerr << "Ignoring metadata, " << songname
+                  << " is not in the library" << std::endl;
+      }
+      break;
+    }
+    case 'n': {
+      // Nickname: size 4B, nickname var
+      uint32_t nickname_size;
+      read(fd, &nickname_size, sizeof(nickname_size));
+      nickname_size = ntohl(nickname_size);
+      std::vector<char> nickname(nickname_size);
+      read(fd, nickname.data(), nickname_size);
+
+      // Keep the roster readable
+      std::string name(nickname.begin(), nickname.end());
+      name = utils.truncate(name, 32);
+      if (!name.empty()) {
+        std::cout << "Nickname: " << name << std::endl;
+        clientManager.setNickname(fd, name);
+      }
+      sendChat("", clientManager.getClient(fd).nickname + " joined", true);
+      break;
+    }
+    case 'r': {
+      // Reaction: kind 1B, broadcast right away so clients can animate it
+      uint8_t kind;
+      read(fd, &kind, sizeof(kind));
+      if (queue.react(kind)) {
+        Json reaction;
+        reaction["from"] = Json(clientManager.getClient(fd).nickname);
+        reaction["kind"] = Json(static_cast<int>(kind));
+        Json message;
+        message["reaction"] = reaction;
+        for (const auto &client : clientManager.getClients()) {
+          sendMessage(client.first, message);
+        }
+      }
+      break;
+    }
+    case 'd': {
+      // Download: name size 4B, name var
+      uint32_t name_size;
+      read(fd, &name_size, sizeof(name_size));
+      name_size = ntohl(name_size);
+      std::vector<char> name(name_size);
+      read(fd, name.data(), name_size);
+
+      // Only songs from the library can be fetched
+      std::string song_name =
+          std::filesystem::path(std::string(name.begin(), name.end()))
+              .filename()
+              .string();
+      std::cout << "Download: " << song_name << std::endl;
+
+      if (song_name.empty() || song_name[0] == '.' ||
+          !std::filesystem::is_regular_file("songs/" + song_name)) {
+        sendDownloadError(fd, song_name);
+        break;
+      }
+      // A large song would hold a pool worker for the whole transfer
+      std::thread([this, fd, song_name]() { sendDownload(fd, song_name); })
+          .detach();
+      break;
+    }
+    case 'c': {
+      // Chat: size 4B, text var
+      uint32_t text_size;
+      read(fd, &text_size, sizeof(text_size));
+      text_size = ntohl(text_size);
+      std::vector<char> text(text_size);
+      read(fd, text.data(), text_size);
+
+      std::string message(text.begin(), text.end());
+      message = utils.truncate(message, 500);
+      if (!message.empty()) {
+        sendChat(clientManager.getClient(fd).nickname, message, false);
+      }
+      break;
+    }
+    case 'h': {
+      std::cout << "Client handshake" << std::endl;
+      uint32_t codecs_size, bitrate;
+      read(fd, &codecs_size, sizeof(codecs_size));
+      codecs_size = ntohl(codecs_size);
+
+      std::string codecs(codecs_size, '\0');
+      read(fd, codecs.data(), codecs_size);
+      read(fd, &bitrate, sizeof(bitrate));
+      bitrate = ntohl(bitrate);
+
+      auto client = clientManager.getClient(fd);
+      std::string codec = client.codec;
+      if (client.streaming) {
+        // The stream can't change codec midway, the client keeps its own
+        std::cout << "Repeated handshake, keeping " << codec << std::endl;
+      } else {
+        codec = Codec::negotiate(codecs);
+        std::cout << "Offered codecs: " << codecs << " chosen: " << codec
+                  << std::endl;
+        std::vector<char> marker = Codec::marker(codec);
+        if (!utils.sendAll(client.audio_fd, marker.data(), marker.size())) {
+          std::cerr << "Can't start the audio stream" << std::endl;
+          break;
+        }
+        clientManager.setCodec(fd, codec, bitrate);
+      }
 
-      std::cout << "Song name: " << songname.data() << std::endl;
-
-      queue.addToQueue(songname.data());
+      Json handshake;
+      handshake["codec"] = Json(codec);
+      handshake["bitrate"] = Json(static_cast<int>(bitrate));
+      Json reply;
+      reply["handshake"] = handshake;
+      sendMessage(fd, reply);
       break;
     }
     }
@@ -359,6 +662,88 @@ public:
     sendUpdate();
   }
 
+  // Messages on the communication socket are newline delimited JSON,
+  // false when the client is gone or stopped reading
+  bool sendMessage(int fd, const Json &message) {
+    std::string line = message.toString() + "\n";
+    std::unique_lock<std::mutex> lock = clientManager.lockSend(fd);
+    return lock && utils.sendAll(fd, line.c_str(), line.size());
+  }
+
+  void sendDownloadError(int fd, const std::string &song_name) {
+    Json download;
+    download["name"] = Json(song_name);
+    download["error"] = Json(std::string("not in the library"));
+    Json reply;
+    reply["download"] = download;
+    sendMessage(fd, reply);
+  }
+
+  // Streams a library song from disk in base64 chunks, which keep the
+  // control socket newline delimited
+  void sendDownload(int fd, const std::string &song_name) {
+    std::string path = "songs/" + song_name;
+    std::error_code error;
+    std::uintmax_t size = std::filesystem::file_size(path, error);
+    std::ifstream song(path, std::ios::binary);
+    if (error || !song.is_open()) {
+      sendDownloadError(fd, song_name);
+      return;
+    }
+    Json download;
+    download["name"] = Json(song_name);
+    // Json numbers are 32 bit, songs may be larger
+    download["size"] = Json(std::to_string(size));
+    download["hash"] = Json(utils.hashFile(path));
+    Json reply;
+    reply["download"] = download;
+    if (!sendMessage(fd, reply)) {
+      return;
+    }
+
+    const size_t download_chunk = 48 * 1024;
+    std::vector<char> buffer(download_chunk);
+    while (song.read(buffer.data(), buffer.size()) || song.gcount() > 0) {
+      Json chunk;
+      chunk["name"] = Json(song_name);
+      chunk["data"] = Json(utils.base64(buffer.data(), song.gcount()));
+      Json message;
+      message["download_chunk"] = chunk;
+      if (!sendMessage(fd, message)) {
+        std::cout << "Download of " << song_name << " stopped" << std::endl;
+        return;
+      }
+    }
+  }
+
+  // Chat line for everyone, system lines report what happened on the radio
+  void sendChat(const std::string &from, const std::string &text,
+                bool system) {
+    Json chat;
+    chat["from"] = Json(from);
+    chat["text"] = Json(text);
+    chat["time"] = Json(static_cast<int>(std::time(nullptr)));
+    chat["system"] = Json(system);
+    Json message;
+    message["chat"] = chat;
+
+    for (const auto &client : clientManager.getClients()) {
+      sendMessage(client.first, message);
+    }
+  }
+
+  // System chat line for a single client, e.g. why its request failed
+  void sendNotice(int fd, const std::string &text) {
+    Json chat;
+    chat["from"] = Json(std::string(""));
+    chat["text"] = Json(text);
+    chat["time"] = Json(static_cast<int>(std::time(nullptr)));
+    chat["system"] = Json(true);
+    Json message;
+    message["chat"] = chat;
+    sendMessage(fd, message);
+  }
+
   void sendUpdate() {
     Json updateJson;
     // Active listener count
@@ -367,13 +752,19 @@ public:
     // Server song library
     updateJson["song_library"] = utils.getSongLibrary();
     updateJson["song_queue"] = queue.getJsonQueue();
+    updateJson["queue_votes"] = queue.getJsonVotes();
+    updateJson["skip_votes"] = Json(queue.getSkipVotes());
+    updateJson["skip_needed"] = Json(static_cast<int>(skipVotesNeeded()));
+    updateJson["queued_by"] = queue.getJsonQueuedBy();
+    updateJson["reactions"] = queue.getJsonReactions();
+    updateJson["listeners"] = clientManager.getJsonListeners();
+    updateJson["song_metadata"] = utils.getSongMetadata();
+    updateJson["song_hashes"] = utils.getSongHashes();
 
-    std::string update = updateJson.toString();
-
-    std::cout << update << std::endl;
+    std::cout << updateJson.toString() << std::endl;
 
     for (const auto &client : clientManager.getClients()) {
-      send(client.first, update.c_str(), update.size(), 0);
+      sendMessage(client.first, updateJson);
     }
   }
 
@@ -402,11 +793,49 @@ public:
       // wait for last chunk playback to end
       std::this_thread::sleep_for(std::chrono::milliseconds(500));
       if (!queue.isEmpty()) {
-        std::vector<char> chunk = queue.getChunk();
+        size_t queued = queue.size();
+        Queue::Chunk chunk;
+        try {
+          chunk = queue.getChunk();
+        } catch (const std::exception &e) {
+          // The song is dropped, the stream goes on with the next one
+          std::cerr << "Stream error: " << e.what() << std::endl;
+          sendChat("", e.what(), true);
+          sendUpdate();
+          continue;
+        }
+        // A finished song changes the queue every client displays
+        if (queue.size() != queued) {
+          sendUpdate();
+        }
+        if (chunk.data.empty()) {
+          continue;
+        }
         for (const auto &client : clientManager.getClients()) {
-          std::cout << "Sending audio chunk size: " << chunk.size()
+          if (!client.second.streaming) {
+            continue;
+          }
+          std::vector<char> payload;
+          if (!client.second.encoder) {
+            payload = chunk.data;
+          } else if (chunk.pcm) {
+            // Opus clients get the samples re-encoded with their own bitrate
+            if (chunk.song_start) {
+              client.second.encoder->reset();
+            }
+            payload = client.second.encoder->encode(
+                chunk.data.data() + chunk.pcm_begin,
+                chunk.pcm_end - chunk.pcm_begin);
+          } else {
+            payload = OpusStream::passthrough(chunk.data);
+          }
+          if (payload.empty()) {
+            continue;
+          }
+          std::cout << "Sending audio chunk size: " << payload.size()
                     << std::endl;
-          if (send(client.second.audio_fd, chunk.data(), chunk.size(), 0) < 0) {
+          if (send(client.second.audio_fd, payload.data(), payload.size(), 0) <
+              0) {
             perror("Audio stream error: ");
           };
         }
@@ -416,9 +845,26 @@ public:
 };
 
 int main(int argc, char *argv[]) {
+  const std::string usage = std::string("Usage: ") + argv[0] +
+                            " <port> <audio port> [skip percent, 1-100]";
+  if (argc < 3) {
+    std::cerr << usage << std::endl;
+    return 1;
+  }
+  // Optional third argument: skip vote threshold in percent of listeners
+  int skip_percent = 50;
+  if (argc > 3) {
+    char *end;
+    long percent = std::strtol(argv[3], &end, 10);
+    if (end == argv[3] || *end != '\0' || percent < 1 || percent > 100) {
+      std::cerr << usage << std::endl;
+      return 1;
+    }
+    skip_percent = static_cast<int>(percent);
+  }
 
   try {
-    JamRadio server = JamRadio(atoi(argv[1]), atoi(argv[2]), 4);
+    JamRadio server = JamRadio(atoi(argv[1]), atoi(argv[2]), 4, skip_percent);
     server.start();
   } catch (const std::exception &e) {
     std::cerr << "Error: " << e.what() << std::endl;